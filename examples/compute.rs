@@ -7,33 +7,24 @@ struct App {
     gpu_buffer_2: Rc<vulkan::multi_buffer::MultiBuffer>,
     globals_1: Rc<vulkan::multi_buffer::MultiBuffer>,
     globals_2: Rc<vulkan::multi_buffer::MultiBuffer>,
-
-    images: Vec<Rc<vulkan::multi_image::MultiImage>>,
+    frame_stats: event_loop::FrameStats,
 
     vulkan: vulkan::Vulkan,
-    _window: window::Window,
+    window: window::Window,
 }
 
 impl App {
-    fn reinitialize_images(&mut self) -> error::VResult<()> {
-        self.images.clear();
-
-        // let vulkan = &mut self.vulkan;
-        // let image_size = vulkan.surface_info.surface_resolution;
-
-        // let image = vulkan.new_multi_image("image", image_size, None)?;
-        // let image_prev = vulkan.prev_shift(&image, "image_prev");
-        // self.images = vec![image, image_prev];
-
-        Ok(())
-    }
-
     fn new(event_loop: &event_loop::EventLoop) -> error::VResult<Self> {
         use vulkan::resources::buffer::BufferUsage;
 
         let shader_paths = vec![std::path::Path::new("examples/shaders/compute.comp")];
-        let window = window::Window::new(event_loop, true)?;
+        let window_config = window::WindowConfig::default()
+            .title("compute-shade-rs")
+            .inner_size(1280, 720);
+        let window = window::Window::with_config(event_loop, &window_config)?;
         let mut vulkan = vulkan::Vulkan::new(&window, &shader_paths, true)?;
+        // Also lets F3 toggle the overlay with the FPS, GPU timings and shader errors.
+        vulkan.set_input(event_loop.input());
 
         let buffer_size = 100;
 
@@ -65,6 +56,14 @@ impl App {
             Some(1),
         )?;
 
+        // Screen-sized images are recreated on resize. A history binds `image` and `image_prev`.
+        // use vulkan::multi_image::{History, SizePolicy};
+        // let history: History<2> = vulkan.new_history(
+        //     "image",
+        //     compute_shade_rs::vk::Format::R32G32B32A32_SFLOAT,
+        //     SizePolicy::Screen,
+        // )?;
+
         let x = 23;
 
         let target_1 = globals_1.mapped(0);
@@ -74,27 +73,23 @@ impl App {
             *target_2.cast::<u32>() = 100 - (100 - x);
         }
 
-        let mut app = Self {
+        Ok(Self {
             gpu_buffer_1,
             gpu_buffer_2,
             globals_1,
             globals_2,
-            images: Vec::new(),
+            frame_stats: event_loop::FrameStats::default(),
             vulkan,
-            _window: window,
-        };
-        app.reinitialize_images()?;
-        Ok(app)
+            window,
+        })
     }
 
     fn run_vulkan(
         &mut self,
         push_constant_values: std::collections::HashMap<String, vulkan::Value>,
     ) -> error::VResult<()> {
-        match unsafe { self.vulkan.tick(&push_constant_values)? } {
-            None => (),
-            Some(vulkan::Event::Resized) => self.reinitialize_images()?,
-        }
+        // Screen-sized images are recreated by `Vulkan` itself, so resize events can be ignored.
+        unsafe { self.vulkan.tick(&push_constant_values)? };
         Ok(())
     }
 }
@@ -136,12 +131,66 @@ impl event_loop::App for App {
     fn handle_event(&mut self, event: &event_loop::Event) -> event_loop::ControlFlow {
         match event {
             event_loop::Event::Close => event_loop::ControlFlow::Exit(0),
+            event_loop::Event::Resize(width, height)
+            | event_loop::Event::ScaleFactorChanged(_, width, height) => {
+                self.vulkan.resize(*width, *height);
+                event_loop::ControlFlow::Continue
+            }
             event_loop::Event::Key(_, winit::event::VirtualKeyCode::Q) => {
                 event_loop::ControlFlow::Exit(0)
             }
+            event_loop::Event::Key(
+                winit::event::ElementState::Pressed,
+                winit::event::VirtualKeyCode::P,
+            ) => {
+                // Cycle through the present modes to compare latency.
+                let modes = self.vulkan.supported_present_modes().to_vec();
+                let index = modes
+                    .iter()
+                    .position(|&mode| mode == self.vulkan.present_mode())
+                    .unwrap_or(0);
+                self.vulkan
+                    .set_present_mode(modes[(index + 1) % modes.len()]);
+                event_loop::ControlFlow::Continue
+            }
+            event_loop::Event::Key(
+                winit::event::ElementState::Pressed,
+                winit::event::VirtualKeyCode::T,
+            ) => {
+                log::info!("{:.1} fps", self.frame_stats.fps());
+                self.vulkan.gpu_timer().print();
+                self.vulkan.pipeline_statistics().print();
+                event_loop::ControlFlow::Continue
+            }
+            event_loop::Event::Key(
+                winit::event::ElementState::Pressed,
+                winit::event::VirtualKeyCode::F11,
+            ) => {
+                self.window
+                    .toggle_fullscreen(window::FullscreenMode::Borderless);
+                event_loop::ControlFlow::Continue
+            }
+            event_loop::Event::DroppedFile(path) => {
+                log::info!("Dropped {}", path.display());
+                event_loop::ControlFlow::Continue
+            }
             _ => event_loop::ControlFlow::Continue,
         }
     }
+
+    fn frame_finished(&mut self, stats: &event_loop::FrameStats) {
+        self.frame_stats = *stats;
+        self.vulkan.set_frame_stats(stats);
+        if stats.frames % 30 == 0 {
+            let status = if self.vulkan.shader_errors().is_empty() {
+                format!("{:.1} fps", stats.fps())
+            } else {
+                "shader error".to_owned()
+            };
+            self.window
+                .set_title(&format!("compute-shade-rs - {status}"));
+        }
+    }
 }
 
 impl Drop for App {
@@ -151,7 +200,8 @@ impl Drop for App {
 }
 
 fn run_main() -> error::VResult<i32> {
-    let event_loop = event_loop::EventLoop::default();
+    // Keeps the immediate present mode from spinning at thousands of frames per second.
+    let event_loop = event_loop::EventLoop::default().target_fps(Some(240.0));
     let mut app = App::new(&event_loop)?;
     Ok(event_loop.run(&mut app))
 }
@@ -0,0 +1,392 @@
+use std::collections::HashMap;
+
+use winit::event::{ElementState, MouseButton, VirtualKeyCode};
+
+use crate::{
+    event_loop::Event,
+    input::{scroll_lines, InputState},
+    vulkan::Value,
+    window::Window,
+};
+
+/// Largest pitch, looking straight up or down makes the view direction parallel to up.
+const MAX_PITCH: f32 = 89f32 * std::f32::consts::PI / 180f32;
+
+type Vec3 = [f32; 3];
+
+fn add(a: Vec3, b: Vec3) -> Vec3 {
+    [a[0] + b[0], a[1] + b[1], a[2] + b[2]]
+}
+
+fn scale(a: Vec3, s: f32) -> Vec3 {
+    [a[0] * s, a[1] * s, a[2] * s]
+}
+
+fn dot(a: Vec3, b: Vec3) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: Vec3, b: Vec3) -> Vec3 {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn normalize(a: Vec3) -> Vec3 {
+    scale(a, 1f32 / dot(a, a).sqrt())
+}
+
+/// Flatten columns into a column-major matrix.
+fn mat4(columns: [[f32; 4]; 4]) -> [f32; 16] {
+    let mut matrix = [0f32; 16];
+    for (index, column) in columns.iter().enumerate() {
+        matrix[index * 4..index * 4 + 4].copy_from_slice(column);
+    }
+    matrix
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CameraMode {
+    /// WASD to move, E/Q or space/control to go up and down, shift to speed up. Hold the right
+    /// mouse button to look around, the wheel changes the speed.
+    FreeFly,
+    /// Drag with the left mouse button to orbit around the target, with the middle button or
+    /// shift held to pan. The wheel zooms.
+    Orbit,
+}
+
+/// Camera data laid out for a `std140` uniform buffer, write it with
+/// `MultiBuffer::write_current`. Matches:
+///
+/// ```glsl
+/// layout(binding = 0) uniform Camera {
+///     mat4 view;
+///     mat4 inverse_view;
+///     mat4 inverse_projection;
+///     vec3 position;
+///     float fov;
+/// } camera;
+/// ```
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct CameraUniform {
+    pub view: [f32; 16],
+    pub inverse_view: [f32; 16],
+    pub inverse_projection: [f32; 16],
+    pub position: [f32; 3],
+    pub fov: f32,
+}
+
+/// A free-fly or orbit camera driven by `event_loop::Event`s and the held keys and buttons of
+/// `EventLoop::input`. The camera looks down `-z` with
+/// `+y` up at yaw and pitch 0. Projections follow Vulkan conventions: `y` points down in
+/// normalized device coordinates and depth ranges from 0 to 1. The ray through pixel `p` of an
+/// image of size `s` starts at `camera_position` and has the direction
+/// `mat3(inverse_view) * (inverse_projection * vec4((p + 0.5) / s * 2 - 1, 1, 1)).xyz`.
+pub struct Camera {
+    pub mode: CameraMode,
+    /// Camera position in free-fly mode, derived from `target` and `distance` when orbiting.
+    pub position: Vec3,
+    /// The point orbited around.
+    pub target: Vec3,
+    pub distance: f32,
+    /// Rotation around `+y` in radians.
+    pub yaw: f32,
+    /// Rotation above the horizon in radians.
+    pub pitch: f32,
+    /// Vertical field of view in radians.
+    pub fov: f32,
+    pub aspect: f32,
+    pub near: f32,
+    pub far: f32,
+    /// Units per second when flying.
+    pub speed: f32,
+    /// Radians per pixel of mouse movement.
+    pub sensitivity: f32,
+
+    cursor: Option<(f32, f32)>,
+    /// Where the cursor is warped to while looking around in free-fly mode.
+    grab_center: Option<(f32, f32)>,
+}
+
+impl Camera {
+    #[must_use]
+    pub fn new(mode: CameraMode) -> Self {
+        Self {
+            mode,
+            position: [0f32, 0f32, 5f32],
+            target: [0f32; 3],
+            distance: 5f32,
+            yaw: 0f32,
+            pitch: 0f32,
+            fov: 60f32.to_radians(),
+            aspect: 1f32,
+            near: 0.1,
+            far: 1000f32,
+            speed: 2f32,
+            sensitivity: 0.003,
+            cursor: None,
+            grab_center: None,
+        }
+    }
+
+    #[must_use]
+    pub fn free_fly() -> Self {
+        Self::new(CameraMode::FreeFly)
+    }
+
+    #[must_use]
+    pub fn orbit() -> Self {
+        Self::new(CameraMode::Orbit)
+    }
+
+    /// Unit vector the camera looks along.
+    #[must_use]
+    pub fn forward(&self) -> Vec3 {
+        [
+            self.pitch.cos() * self.yaw.sin(),
+            self.pitch.sin(),
+            -self.pitch.cos() * self.yaw.cos(),
+        ]
+    }
+
+    fn basis(&self) -> (Vec3, Vec3, Vec3) {
+        let forward = self.forward();
+        let right = normalize(cross(forward, [0f32, 1f32, 0f32]));
+        let up = cross(right, forward);
+        (right, up, forward)
+    }
+
+    /// The camera position in world space in both modes.
+    #[must_use]
+    pub fn eye(&self) -> Vec3 {
+        match self.mode {
+            CameraMode::FreeFly => self.position,
+            CameraMode::Orbit => add(self.target, scale(self.forward(), -self.distance)),
+        }
+    }
+
+    /// World to camera transform, column-major.
+    #[must_use]
+    pub fn view(&self) -> [f32; 16] {
+        let (r, u, f) = self.basis();
+        let p = self.eye();
+        mat4([
+            [r[0], u[0], -f[0], 0f32],
+            [r[1], u[1], -f[1], 0f32],
+            [r[2], u[2], -f[2], 0f32],
+            [-dot(r, p), -dot(u, p), dot(f, p), 1f32],
+        ])
+    }
+
+    /// Camera to world transform, column-major.
+    #[must_use]
+    pub fn inverse_view(&self) -> [f32; 16] {
+        let (r, u, f) = self.basis();
+        let p = self.eye();
+        mat4([
+            [r[0], r[1], r[2], 0f32],
+            [u[0], u[1], u[2], 0f32],
+            [-f[0], -f[1], -f[2], 0f32],
+            [p[0], p[1], p[2], 1f32],
+        ])
+    }
+
+    fn projection_factors(&self) -> (f32, f32, f32, f32) {
+        let focal_length = 1f32 / (self.fov / 2f32).tan();
+        let x = focal_length / self.aspect;
+        let y = -focal_length;
+        let z = self.far / (self.near - self.far);
+        let w = self.near * self.far / (self.near - self.far);
+        (x, y, z, w)
+    }
+
+    /// Perspective projection, column-major.
+    #[must_use]
+    pub fn projection(&self) -> [f32; 16] {
+        let (x, y, z, w) = self.projection_factors();
+        mat4([
+            [x, 0f32, 0f32, 0f32],
+            [0f32, y, 0f32, 0f32],
+            [0f32, 0f32, z, -1f32],
+            [0f32, 0f32, w, 0f32],
+        ])
+    }
+
+    /// Maps normalized device coordinates to camera space, column-major.
+    #[must_use]
+    pub fn inverse_projection(&self) -> [f32; 16] {
+        let (x, y, z, w) = self.projection_factors();
+        mat4([
+            [1f32 / x, 0f32, 0f32, 0f32],
+            [0f32, 1f32 / y, 0f32, 0f32],
+            [0f32, 0f32, 0f32, 1f32 / w],
+            [0f32, 0f32, -1f32, z / w],
+        ])
+    }
+
+    #[must_use]
+    pub fn uniform(&self) -> CameraUniform {
+        CameraUniform {
+            view: self.view(),
+            inverse_view: self.inverse_view(),
+            inverse_projection: self.inverse_projection(),
+            position: self.eye(),
+            fov: self.fov,
+        }
+    }
+
+    /// Insert the push constants `camera_position` (`vec3`), `camera_view`,
+    /// `camera_inverse_view`, `camera_inverse_projection` (`mat4`) and `camera_fov` (`float`).
+    /// Shaders declare the fields they need, three matrices exceed the guaranteed 128 bytes of
+    /// push constants.
+    pub fn insert_push_constants(&self, push_constant_values: &mut HashMap<String, Value>) {
+        for (name, value) in [
+            ("camera_position", Value::Vec3(self.eye())),
+            ("camera_view", Value::Mat4(self.view())),
+            ("camera_inverse_view", Value::Mat4(self.inverse_view())),
+            (
+                "camera_inverse_projection",
+                Value::Mat4(self.inverse_projection()),
+            ),
+            ("camera_fov", Value::F32(self.fov)),
+        ] {
+            push_constant_values.insert(name.to_owned(), value);
+        }
+    }
+
+    fn rotate(&mut self, delta_x: f32, delta_y: f32) {
+        self.yaw += delta_x * self.sensitivity;
+        self.pitch = (self.pitch - delta_y * self.sensitivity).clamp(-MAX_PITCH, MAX_PITCH);
+    }
+
+    fn set_looking(&mut self, looking: bool, window: &Window) {
+        window.set_cursor_grab(looking);
+        self.grab_center = if looking {
+            let (width, height) = window.size();
+            #[allow(clippy::cast_precision_loss)]
+            Some((width as f32 / 2f32, height as f32 / 2f32))
+        } else {
+            None
+        };
+        if let Some((x, y)) = self.grab_center {
+            #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+            window.set_cursor_position(x as u32, y as u32);
+            self.cursor = Some((x, y));
+        }
+    }
+
+    /// Update the camera from an input event, grabbing the cursor of `window` while looking
+    /// around. `input` must already include the event, as passed to `App::handle_event`.
+    /// Returns whether the view changed.
+    pub fn handle_event(&mut self, event: &Event, window: &Window, input: &InputState) -> bool {
+        match event {
+            Event::Resize(width, height) | Event::ScaleFactorChanged(_, width, height) => {
+                if *width > 0 && *height > 0 {
+                    #[allow(clippy::cast_precision_loss)]
+                    let aspect = *width as f32 / *height as f32;
+                    self.aspect = aspect;
+                }
+                true
+            }
+            Event::Focus(false) => {
+                if self.grab_center.is_some() {
+                    self.set_looking(false, window);
+                }
+                false
+            }
+            Event::MouseButton(state, button) => {
+                if self.mode == CameraMode::FreeFly && *button == MouseButton::Right {
+                    self.set_looking(*state == ElementState::Pressed, window);
+                }
+                false
+            }
+            Event::MouseMove(x, y) => self.mouse_move(*x, *y, window, input),
+            Event::MouseWheel(delta) => {
+                let (_, lines) = scroll_lines(delta);
+                match self.mode {
+                    CameraMode::FreeFly => self.speed *= 1.1f32.powf(lines),
+                    CameraMode::Orbit => self.distance *= 0.9f32.powf(lines),
+                }
+                self.mode == CameraMode::Orbit
+            }
+            _ => false,
+        }
+    }
+
+    /// The cursor is tracked separately from `input`, since warping it back to the center
+    /// while looking around must not count as movement.
+    fn mouse_move(&mut self, x: f32, y: f32, window: &Window, input: &InputState) -> bool {
+        let previous = self.cursor.replace((x, y));
+        let Some((previous_x, previous_y)) = previous else {
+            return false;
+        };
+        let (delta_x, delta_y) = (x - previous_x, y - previous_y);
+        match self.mode {
+            CameraMode::FreeFly => {
+                let Some((center_x, center_y)) = self.grab_center else {
+                    return false;
+                };
+                self.rotate(delta_x, delta_y);
+                // Keep the grabbed cursor away from the window border.
+                #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+                window.set_cursor_position(center_x as u32, center_y as u32);
+                self.cursor = Some((center_x, center_y));
+                true
+            }
+            CameraMode::Orbit => {
+                let pan = input.is_down(MouseButton::Middle)
+                    || (input.modifiers().shift() && input.is_down(MouseButton::Left));
+                if pan {
+                    let (right, up, _) = self.basis();
+                    let units_per_pixel = self.distance * self.sensitivity;
+                    self.target = add(self.target, scale(right, -delta_x * units_per_pixel));
+                    self.target = add(self.target, scale(up, delta_y * units_per_pixel));
+                    true
+                } else if input.is_down(MouseButton::Left) {
+                    self.rotate(delta_x, delta_y);
+                    true
+                } else {
+                    false
+                }
+            }
+        }
+    }
+
+    /// Move according to the keys held in `input`, `dt` is the frame time in seconds. Returns
+    /// whether the camera moved.
+    pub fn update(&mut self, dt: f32, input: &InputState) -> bool {
+        if self.mode != CameraMode::FreeFly {
+            return false;
+        }
+        let held = |keys: &[VirtualKeyCode]| keys.iter().any(|key| input.is_down(*key));
+        let axis = |positive: &[VirtualKeyCode], negative: &[VirtualKeyCode]| {
+            f32::from(u8::from(held(positive))) - f32::from(u8::from(held(negative)))
+        };
+        let forward_amount = axis(&[VirtualKeyCode::W], &[VirtualKeyCode::S]);
+        let right_amount = axis(&[VirtualKeyCode::D], &[VirtualKeyCode::A]);
+        let up_amount = axis(
+            &[VirtualKeyCode::E, VirtualKeyCode::Space],
+            &[VirtualKeyCode::Q, VirtualKeyCode::LControl],
+        );
+        if forward_amount == 0f32 && right_amount == 0f32 && up_amount == 0f32 {
+            return false;
+        }
+
+        let boost = if held(&[VirtualKeyCode::LShift]) {
+            4f32
+        } else {
+            1f32
+        };
+        let step = self.speed * boost * dt;
+        let (right, _, forward) = self.basis();
+        let mut position = self.position;
+        position = add(position, scale(forward, forward_amount * step));
+        position = add(position, scale(right, right_amount * step));
+        position = add(position, scale([0f32, 1f32, 0f32], up_amount * step));
+        self.position = position;
+        true
+    }
+}
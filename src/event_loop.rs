@@ -1,10 +1,18 @@
 use std::ops::{Deref, DerefMut};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
 
 use winit::dpi::PhysicalPosition;
-use winit::event::{ElementState, MouseButton, VirtualKeyCode};
+use winit::event::{ElementState, ModifiersState, MouseButton, MouseScrollDelta, VirtualKeyCode};
 use winit::platform::run_return::EventLoopExtRunReturn;
+use winit::window::WindowId;
 use winit::{event, event_loop};
 
+use crate::{cell::Cell, input::InputState, utils::mix};
+
+/// Sleeping is imprecise, the last part of the wait before a frame is spent spinning.
+const SPIN_MARGIN: Duration = Duration::from_millis(2);
+
 pub enum ControlFlow {
     Continue,
     Exit(i32),
@@ -21,10 +29,23 @@ impl From<ControlFlow> for event_loop::ControlFlow {
 
 pub enum Event {
     Close,
-    Resize,
+    /// The new inner size of the window in physical pixels.
+    Resize(u32, u32),
+    /// The new scale factor and the new inner size of the window in physical pixels.
+    ScaleFactorChanged(f64, u32, u32),
     Key(ElementState, VirtualKeyCode),
+    /// The state of shift, control, alt and logo after it changed.
+    Modifiers(ModifiersState),
+    /// A character typed, after applying the keyboard layout and modifiers.
+    Char(char),
     MouseButton(ElementState, MouseButton),
     MouseMove(f32, f32),
+    MouseWheel(MouseScrollDelta),
+    CursorEntered,
+    CursorLeft,
+    /// Whether the window gained or lost focus.
+    Focus(bool),
+    DroppedFile(PathBuf),
 }
 
 impl<'a> TryFrom<&'a event::Event<'a, ()>> for Event {
@@ -36,6 +57,22 @@ impl<'a> TryFrom<&'a event::Event<'a, ()>> for Event {
                 event: event::WindowEvent::CloseRequested,
                 ..
             } => Ok(Event::Close),
+            event::Event::WindowEvent {
+                event: event::WindowEvent::Resized(size),
+                ..
+            } => Ok(Event::Resize(size.width, size.height)),
+            event::Event::WindowEvent {
+                event:
+                    event::WindowEvent::ScaleFactorChanged {
+                        scale_factor,
+                        new_inner_size,
+                    },
+                ..
+            } => Ok(Event::ScaleFactorChanged(
+                *scale_factor,
+                new_inner_size.width,
+                new_inner_size.height,
+            )),
             event::Event::WindowEvent {
                 event:
                     event::WindowEvent::KeyboardInput {
@@ -49,6 +86,14 @@ impl<'a> TryFrom<&'a event::Event<'a, ()>> for Event {
                     },
                 ..
             } => Ok(Event::Key(*state, *key)),
+            event::Event::WindowEvent {
+                event: event::WindowEvent::ModifiersChanged(modifiers),
+                ..
+            } => Ok(Event::Modifiers(*modifiers)),
+            event::Event::WindowEvent {
+                event: event::WindowEvent::ReceivedCharacter(c),
+                ..
+            } => Ok(Event::Char(*c)),
             event::Event::WindowEvent {
                 event: event::WindowEvent::MouseInput { state, button, .. },
                 ..
@@ -61,24 +106,93 @@ impl<'a> TryFrom<&'a event::Event<'a, ()>> for Event {
                     },
                 ..
             } => Ok(Event::MouseMove(*x as f32, *y as f32)),
+            event::Event::WindowEvent {
+                event: event::WindowEvent::MouseWheel { delta, .. },
+                ..
+            } => Ok(Event::MouseWheel(*delta)),
+            event::Event::WindowEvent {
+                event: event::WindowEvent::CursorEntered { .. },
+                ..
+            } => Ok(Event::CursorEntered),
+            event::Event::WindowEvent {
+                event: event::WindowEvent::CursorLeft { .. },
+                ..
+            } => Ok(Event::CursorLeft),
+            event::Event::WindowEvent {
+                event: event::WindowEvent::Focused(focused),
+                ..
+            } => Ok(Event::Focus(*focused)),
+            event::Event::WindowEvent {
+                event: event::WindowEvent::DroppedFile(path),
+                ..
+            } => Ok(Event::DroppedFile(path.clone())),
             _ => Err(()),
         }
     }
 }
 
+/// Frame timing measured by the event loop, passed to `App::frame_finished`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FrameStats {
+    /// Number of ticks so far.
+    pub frames: u64,
+    /// Time between the starts of the last two ticks.
+    pub frame_time: Duration,
+    /// Time spent in the last tick, excluding the limiter.
+    pub work_time: Duration,
+    /// Exponential moving average of `frame_time` in seconds.
+    pub average_frame_time: f32,
+}
+
+impl FrameStats {
+    #[must_use]
+    pub fn fps(&self) -> f32 {
+        if self.average_frame_time > 0f32 {
+            1f32 / self.average_frame_time
+        } else {
+            0f32
+        }
+    }
+}
+
 pub trait App {
     fn tick(&mut self) -> ControlFlow;
     fn handle_event(&mut self, event: &Event) -> ControlFlow;
+
+    /// Like `handle_event`, with the window the event was sent to. Implement it instead of
+    /// `handle_event` to tell several windows apart, see `Window::id`.
+    fn handle_window_event(&mut self, _window_id: WindowId, event: &Event) -> ControlFlow {
+        self.handle_event(event)
+    }
+
+    /// Checked in on-demand mode when no input or redraw request arrived, and polled every
+    /// `EventLoop::poll_interval` while idle, e.g. `Vulkan::needs_redraw`.
+    fn needs_redraw(&self) -> bool {
+        false
+    }
+
+    /// Called after every tick.
+    fn frame_finished(&mut self, _stats: &FrameStats) {}
 }
 
 pub struct EventLoop {
     inner: event_loop::EventLoop<()>,
+    input: Cell<InputState>,
+    target_fps: Option<f32>,
+    on_demand: bool,
+    poll_interval: Option<Duration>,
+    stats_alpha: f32,
 }
 
 impl Default for EventLoop {
     fn default() -> Self {
         EventLoop {
             inner: event_loop::EventLoop::new(),
+            input: Cell::new(InputState::default()),
+            target_fps: None,
+            on_demand: false,
+            poll_interval: Some(Duration::from_millis(500)),
+            stats_alpha: 0.95,
         }
     }
 }
@@ -97,20 +211,163 @@ impl DerefMut for EventLoop {
     }
 }
 
-fn handle_event<A: App>(event: &event::Event<()>, app: &mut A) -> ControlFlow {
-    match event {
-        event::Event::MainEventsCleared => app.tick(),
-        _ => event.try_into().map_or_else(
-            |_| ControlFlow::Continue,
-            |nice_event| app.handle_event(&nice_event),
-        ),
+/// Tracks when the next tick is due.
+struct Pacer {
+    frame_period: Option<Duration>,
+    on_demand: bool,
+    poll_interval: Option<Duration>,
+    stats_alpha: f32,
+    next_frame: Option<Instant>,
+    last_frame_start: Option<Instant>,
+    redraw: bool,
+    stats: FrameStats,
+    input: Cell<InputState>,
+}
+
+impl Pacer {
+    /// Sleep, then spin until the next frame is due.
+    fn limit(&mut self) {
+        let Some(frame_period) = self.frame_period else {
+            return;
+        };
+        let now = Instant::now();
+        let Some(next_frame) = self.next_frame else {
+            self.next_frame = Some(now + frame_period);
+            return;
+        };
+        if next_frame > now + SPIN_MARGIN {
+            std::thread::sleep(next_frame - now - SPIN_MARGIN);
+        }
+        while Instant::now() < next_frame {
+            std::hint::spin_loop();
+        }
+        // Don't catch up on missed frames.
+        self.next_frame = Some((next_frame + frame_period).max(Instant::now()));
+    }
+
+    fn tick<A: App>(&mut self, app: &mut A) -> ControlFlow {
+        self.limit();
+        let start = Instant::now();
+        let control_flow = app.tick();
+        self.input.as_mut_ref().end_frame();
+
+        let stats = &mut self.stats;
+        stats.work_time = start.elapsed();
+        if let Some(last_frame_start) = self.last_frame_start {
+            stats.frame_time = start - last_frame_start;
+            let frame_time = stats.frame_time.as_secs_f32();
+            stats.average_frame_time = if stats.frames <= 1 {
+                frame_time
+            } else {
+                mix(stats.average_frame_time, frame_time, self.stats_alpha)
+            };
+        }
+        stats.frames += 1;
+        self.last_frame_start = Some(start);
+        app.frame_finished(&self.stats);
+        control_flow
+    }
+
+    fn handle_event<A: App>(&mut self, event: &event::Event<()>, app: &mut A) -> ControlFlow {
+        match event {
+            event::Event::MainEventsCleared => {
+                let redraw = !self.on_demand || self.redraw || app.needs_redraw();
+                if redraw {
+                    self.redraw = false;
+                    self.tick(app)
+                } else {
+                    ControlFlow::Continue
+                }
+            }
+            // Redraw requests, see `Vulkan::set_event_loop_proxy`.
+            event::Event::UserEvent(()) => {
+                self.redraw = true;
+                ControlFlow::Continue
+            }
+            event::Event::WindowEvent { window_id, .. } => event.try_into().map_or_else(
+                |_| ControlFlow::Continue,
+                |nice_event| {
+                    self.redraw = true;
+                    self.input
+                        .as_mut_ref()
+                        .handle_window_event(*window_id, &nice_event);
+                    app.handle_window_event(*window_id, &nice_event)
+                },
+            ),
+            _ => ControlFlow::Continue,
+        }
+    }
+
+    fn control_flow(&self, control_flow: ControlFlow) -> event_loop::ControlFlow {
+        match control_flow {
+            // Redraw requests wake the loop, polling `App::needs_redraw` only picks up shader
+            // hot reloads.
+            ControlFlow::Continue if self.on_demand => match self.poll_interval {
+                Some(poll_interval) => {
+                    event_loop::ControlFlow::WaitUntil(Instant::now() + poll_interval)
+                }
+                None => event_loop::ControlFlow::Wait,
+            },
+            control_flow => control_flow.into(),
+        }
     }
 }
 
 impl EventLoop {
+    /// Handle to the keyboard and mouse state, updated before `App::handle_event` and reset
+    /// after every `App::tick`. Pass it to `Vulkan::set_input` for the mouse built-ins.
+    #[must_use]
+    pub fn input(&self) -> Cell<InputState> {
+        self.input.clone()
+    }
+
+    /// Limit the tick rate, `None` (the default) ticks as fast as possible.
+    #[must_use]
+    pub fn target_fps(mut self, target_fps: Option<f32>) -> Self {
+        self.target_fps = target_fps.filter(|fps| *fps > 0f32);
+        self
+    }
+
+    /// Only tick after input events, redraw requests sent through `create_proxy` (see
+    /// `Vulkan::set_event_loop_proxy`) or if `App::needs_redraw`.
+    #[must_use]
+    pub fn on_demand(mut self, on_demand: bool) -> Self {
+        self.on_demand = on_demand;
+        self
+    }
+
+    /// How often `App::needs_redraw` is polled while idle in on-demand mode, which is only
+    /// needed to pick up modified shaders. `None` waits for events only.
+    #[must_use]
+    pub fn poll_interval(mut self, poll_interval: Option<Duration>) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// Smoothing factor of `FrameStats::average_frame_time`, as in `Timer::new`.
+    #[must_use]
+    pub fn stats_alpha(mut self, stats_alpha: f32) -> Self {
+        self.stats_alpha = stats_alpha;
+        self
+    }
+
     pub fn run<A: App>(mut self, app: &mut A) -> i32 {
+        let mut pacer = Pacer {
+            frame_period: self
+                .target_fps
+                .map(|fps| Duration::from_secs_f32(1f32 / fps)),
+            on_demand: self.on_demand,
+            poll_interval: self.poll_interval,
+            stats_alpha: self.stats_alpha,
+            next_frame: None,
+            last_frame_start: None,
+            redraw: true,
+            stats: FrameStats::default(),
+            input: self.input.clone(),
+        };
         self.run_return(|event, &_, control_flow| {
-            *control_flow = handle_event(&event, app).into();
+            let flow = pacer.handle_event(&event, app);
+            *control_flow = pacer.control_flow(flow);
         })
     }
 }
@@ -0,0 +1,210 @@
+use std::collections::HashSet;
+
+use winit::{
+    event::{ElementState, ModifiersState, MouseButton, MouseScrollDelta, VirtualKeyCode},
+    window::WindowId,
+};
+
+use crate::event_loop::Event;
+
+/// Pixels per line when converting pixel scroll deltas, as reported by touchpads.
+const PIXELS_PER_LINE: f32 = 20f32;
+
+/// Horizontal and vertical wheel movement in lines.
+pub(crate) fn scroll_lines(delta: &MouseScrollDelta) -> (f32, f32) {
+    match delta {
+        MouseScrollDelta::LineDelta(x, y) => (*x, *y),
+        #[allow(clippy::cast_possible_truncation)]
+        MouseScrollDelta::PixelDelta(position) => (
+            position.x as f32 / PIXELS_PER_LINE,
+            position.y as f32 / PIXELS_PER_LINE,
+        ),
+    }
+}
+
+/// A key or a mouse button.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Button {
+    Key(VirtualKeyCode),
+    Mouse(MouseButton),
+}
+
+impl From<VirtualKeyCode> for Button {
+    fn from(value: VirtualKeyCode) -> Self {
+        Self::Key(value)
+    }
+}
+
+impl From<MouseButton> for Button {
+    fn from(value: MouseButton) -> Self {
+        Self::Mouse(value)
+    }
+}
+
+/// Keyboard and mouse state maintained by `EventLoop`, see `EventLoop::input`. The `just_*`
+/// queries, the mouse delta and the wheel delta cover the events since the previous tick.
+#[derive(Debug, Default)]
+pub struct InputState {
+    down: HashSet<Button>,
+    pressed: HashSet<Button>,
+    released: HashSet<Button>,
+    modifiers: ModifiersState,
+    mouse_position: (f32, f32),
+    mouse_delta: (f32, f32),
+    wheel_delta: (f32, f32),
+    wheel: (f32, f32),
+    cursor_inside: bool,
+    /// The window the cursor positions refer to.
+    cursor_window: Option<WindowId>,
+    focused: bool,
+}
+
+impl InputState {
+    /// Like `handle_event`, but also tracks which window the cursor is in.
+    pub fn handle_window_event(&mut self, window_id: WindowId, event: &Event) {
+        match event {
+            Event::MouseMove(..) | Event::CursorEntered => {
+                // Positions in another window are not comparable.
+                if self.cursor_window != Some(window_id) {
+                    self.cursor_inside = false;
+                }
+                self.cursor_window = Some(window_id);
+            }
+            Event::CursorLeft if self.cursor_window != Some(window_id) => return,
+            Event::CursorLeft => self.cursor_window = None,
+            _ => {}
+        }
+        self.handle_event(event);
+    }
+
+    pub fn handle_event(&mut self, event: &Event) {
+        match event {
+            Event::Key(state, key) => self.set_button((*key).into(), *state),
+            Event::MouseButton(state, button) => self.set_button((*button).into(), *state),
+            Event::Modifiers(modifiers) => self.modifiers = *modifiers,
+            Event::MouseMove(x, y) => {
+                // The position is stale after entering, count deltas from the first move.
+                let (previous_x, previous_y) = self.mouse_position;
+                if self.cursor_inside {
+                    self.mouse_delta.0 += x - previous_x;
+                    self.mouse_delta.1 += y - previous_y;
+                }
+                self.mouse_position = (*x, *y);
+                self.cursor_inside = true;
+            }
+            Event::MouseWheel(delta) => {
+                let (x, y) = scroll_lines(delta);
+                self.wheel_delta.0 += x;
+                self.wheel_delta.1 += y;
+                self.wheel.0 += x;
+                self.wheel.1 += y;
+            }
+            Event::CursorLeft => self.cursor_inside = false,
+            Event::Focus(focused) => {
+                self.focused = *focused;
+                // Releases are not delivered to unfocused windows.
+                if !focused {
+                    self.released.extend(self.down.drain());
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn set_button(&mut self, button: Button, state: ElementState) {
+        match state {
+            ElementState::Pressed => {
+                // Ignore key repeats.
+                if self.down.insert(button) {
+                    self.pressed.insert(button);
+                }
+            }
+            ElementState::Released => {
+                if self.down.remove(&button) {
+                    self.released.insert(button);
+                }
+            }
+        }
+    }
+
+    /// Start a new tick, resetting the per-tick state.
+    pub fn end_frame(&mut self) {
+        self.pressed.clear();
+        self.released.clear();
+        self.mouse_delta = (0f32, 0f32);
+        self.wheel_delta = (0f32, 0f32);
+    }
+
+    #[must_use]
+    pub fn is_down(&self, button: impl Into<Button>) -> bool {
+        self.down.contains(&button.into())
+    }
+
+    /// Pressed since the previous tick.
+    #[must_use]
+    pub fn just_pressed(&self, button: impl Into<Button>) -> bool {
+        self.pressed.contains(&button.into())
+    }
+
+    /// Released since the previous tick.
+    #[must_use]
+    pub fn just_released(&self, button: impl Into<Button>) -> bool {
+        self.released.contains(&button.into())
+    }
+
+    #[must_use]
+    pub fn modifiers(&self) -> ModifiersState {
+        self.modifiers
+    }
+
+    /// Cursor position in physical pixels relative to the top left of the window.
+    #[must_use]
+    pub fn mouse_position(&self) -> (f32, f32) {
+        self.mouse_position
+    }
+
+    /// Cursor movement since the previous tick in physical pixels.
+    #[must_use]
+    pub fn mouse_delta(&self) -> (f32, f32) {
+        self.mouse_delta
+    }
+
+    /// Wheel movement since the previous tick in lines.
+    #[must_use]
+    pub fn wheel_delta(&self) -> (f32, f32) {
+        self.wheel_delta
+    }
+
+    /// Wheel movement accumulated since the start in lines.
+    #[must_use]
+    pub fn wheel(&self) -> (f32, f32) {
+        self.wheel
+    }
+
+    /// Whether the cursor moved inside the window since it last entered.
+    #[must_use]
+    pub fn cursor_inside(&self) -> bool {
+        self.cursor_inside
+    }
+
+    /// The window the cursor is in, if the events were passed to `handle_window_event`.
+    #[must_use]
+    pub fn cursor_window(&self) -> Option<WindowId> {
+        self.cursor_window
+    }
+
+    #[must_use]
+    pub fn focused(&self) -> bool {
+        self.focused
+    }
+
+    /// Held mouse buttons as bits, left is 1, right 2 and middle 4.
+    #[must_use]
+    pub fn mouse_buttons(&self) -> u32 {
+        [MouseButton::Left, MouseButton::Right, MouseButton::Middle]
+            .into_iter()
+            .enumerate()
+            .filter(|(_, button)| self.is_down(*button))
+            .fold(0, |bits, (index, _)| bits | (1 << index))
+    }
+}
@@ -1,9 +1,11 @@
 pub use winit;
 pub use ash::vk;
 
+pub mod camera;
 pub mod cell;
 pub mod error;
 pub mod event_loop;
+pub mod input;
 pub mod ring_buffer;
 pub mod thread_shared;
 pub mod timer;
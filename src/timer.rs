@@ -1,55 +1,379 @@
-use std::{collections::HashMap, time};
+use std::{
+    borrow::Cow,
+    collections::{HashMap, VecDeque},
+    fmt::Write,
+    time,
+};
 
 use log::info;
 
 use crate::utils::mix;
 
+/// Number of samples per section used for percentiles and histograms.
+pub const DEFAULT_WINDOW: usize = 256;
+/// Number of recorded sections kept for the trace export.
+pub const DEFAULT_TRACE_CAPACITY: usize = 65536;
+
+/// Summary of a section over the rolling window, durations in milliseconds. Mean and standard
+/// deviation are exponential moving averages.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SectionStats {
+    /// Names of the enclosing scopes and the section, joined by `/`.
+    pub name: String,
+    /// Number of enclosing scopes.
+    pub depth: usize,
+    pub mean: f32,
+    pub std_dev: f32,
+    pub p50: f32,
+    pub p95: f32,
+    pub p99: f32,
+    pub max: f32,
+    pub samples: usize,
+}
+
+struct Section {
+    name: String,
+    depth: usize,
+    avg_delta: f32,
+    avg_square_delta: f32,
+    /// The most recent durations in seconds.
+    window: VecDeque<f32>,
+}
+
+struct Scope {
+    name: String,
+    start: time::Instant,
+}
+
+struct TraceEvent {
+    name: String,
+    start: time::Duration,
+    duration: time::Duration,
+}
+
+/// Measures CPU wall-clock time of consecutive sections. `section` ends a section which started
+/// at the end of the previous one, `begin` and `end` enclose nested sections in a scope.
 pub struct Timer {
     alpha: f32,
+    window: usize,
+    trace_capacity: usize,
+    epoch: time::Instant,
     last_section_end: time::Instant,
-    section_order: Vec<*const u8>,
-    sections: HashMap<*const u8, (&'static str, f32, f32)>,
+    scopes: Vec<Scope>,
+    section_order: Vec<String>,
+    sections: HashMap<String, Section>,
+    trace: VecDeque<TraceEvent>,
+}
+
+fn percentile(sorted: &[f32], p: f32) -> f32 {
+    if sorted.is_empty() {
+        return 0f32;
+    }
+    // Nearest rank.
+    #[allow(
+        clippy::cast_precision_loss,
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss
+    )]
+    let rank = (p * sorted.len() as f32).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+fn json_string(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len() + 2);
+    escaped.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if c.is_control() => {
+                let _ = write!(escaped, "\\u{:04x}", c as u32);
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+fn csv_field(value: &str) -> Cow<'_, str> {
+    if value.contains([',', '"', '\n']) {
+        Cow::Owned(format!("\"{}\"", value.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(value)
+    }
 }
 
 impl Timer {
     #[must_use]
     pub fn new(alpha: f32) -> Timer {
+        let now = time::Instant::now();
         Timer {
             alpha,
-            last_section_end: time::Instant::now(),
+            window: DEFAULT_WINDOW,
+            trace_capacity: DEFAULT_TRACE_CAPACITY,
+            epoch: now,
+            last_section_end: now,
+            scopes: Vec::new(),
             section_order: Vec::new(),
             sections: HashMap::new(),
+            trace: VecDeque::new(),
         }
     }
 
+    /// Number of samples per section used for percentiles and histograms.
+    #[must_use]
+    pub fn window(mut self, window: usize) -> Self {
+        self.window = window.max(1);
+        self
+    }
+
+    /// Number of recorded sections kept for `to_chrome_trace`, 0 disables tracing.
+    #[must_use]
+    pub fn trace_capacity(mut self, trace_capacity: usize) -> Self {
+        self.trace_capacity = trace_capacity;
+        self
+    }
+
     pub fn start(&mut self) {
         self.last_section_end = time::Instant::now();
     }
 
-    pub fn section(&mut self, name: &'static str) {
-        let delta = self.last_section_end.elapsed().as_secs_f32();
-        let key = name.as_ptr();
-        match self.sections.get_mut(&key) {
+    /// End the section which started at the end of the previous section, `start` or `begin`.
+    pub fn section(&mut self, name: impl Into<Cow<'static, str>>) {
+        let now = time::Instant::now();
+        let path = self.path(&name.into());
+        self.record(path, self.scopes.len(), self.last_section_end, now);
+        self.last_section_end = now;
+    }
+
+    /// Open a scope, sections until the matching `end` are nested in it.
+    pub fn begin(&mut self, name: impl Into<Cow<'static, str>>) {
+        let now = time::Instant::now();
+        let name = self.path(&name.into());
+        // List scopes before their nested sections.
+        if !self.section_order.contains(&name) {
+            self.section_order.push(name.clone());
+        }
+        self.scopes.push(Scope { name, start: now });
+        self.last_section_end = now;
+    }
+
+    /// Close the innermost scope, recording it as a section spanning from `begin`.
+    pub fn end(&mut self) {
+        let Some(Scope { name, start }) = self.scopes.pop() else {
+            return;
+        };
+        let now = time::Instant::now();
+        self.record(name, self.scopes.len(), start, now);
+        self.last_section_end = now;
+    }
+
+    fn path(&self, name: &str) -> String {
+        match self.scopes.last() {
+            Some(scope) => format!("{}/{name}", scope.name),
+            None => name.to_owned(),
+        }
+    }
+
+    fn record(&mut self, path: String, depth: usize, start: time::Instant, end: time::Instant) {
+        let duration = end - start;
+        let delta = duration.as_secs_f32();
+
+        if self.trace_capacity > 0 {
+            if self.trace.len() == self.trace_capacity {
+                self.trace.pop_front();
+            }
+            self.trace.push_back(TraceEvent {
+                name: path.clone(),
+                start: start - self.epoch,
+                duration,
+            });
+        }
+
+        match self.sections.get_mut(&path) {
             None => {
-                self.sections.insert(key, (name, delta, delta.powf(2f32)));
-                self.section_order.push(key);
+                let section = Section {
+                    name: path.clone(),
+                    depth,
+                    avg_delta: delta,
+                    avg_square_delta: delta.powf(2f32),
+                    window: VecDeque::from([delta]),
+                };
+                if !self.section_order.contains(&path) {
+                    self.section_order.push(path.clone());
+                }
+                self.sections.insert(path, section);
             }
-            Some((_, avg_delta, avg_square_delta)) => {
-                *avg_delta = mix(*avg_delta, delta, self.alpha);
-                *avg_square_delta = mix(*avg_square_delta, delta.powf(2f32), self.alpha);
+            Some(section) => {
+                section.avg_delta = mix(section.avg_delta, delta, self.alpha);
+                section.avg_square_delta =
+                    mix(section.avg_square_delta, delta.powf(2f32), self.alpha);
+                if section.window.len() == self.window {
+                    section.window.pop_front();
+                }
+                section.window.push_back(delta);
             }
         }
+    }
+
+    fn summarize(section: &Section) -> SectionStats {
+        let mut sorted = section.window.iter().copied().collect::<Vec<_>>();
+        sorted.sort_by(f32::total_cmp);
+        let variance = (section.avg_square_delta - section.avg_delta.powf(2f32)).max(0f32);
+        SectionStats {
+            name: section.name.clone(),
+            depth: section.depth,
+            mean: section.avg_delta * 1000f32,
+            std_dev: variance.sqrt() * 1000f32,
+            p50: percentile(&sorted, 0.5) * 1000f32,
+            p95: percentile(&sorted, 0.95) * 1000f32,
+            p99: percentile(&sorted, 0.99) * 1000f32,
+            max: sorted.last().copied().unwrap_or_default() * 1000f32,
+            samples: sorted.len(),
+        }
+    }
 
-        self.start();
+    /// Statistics of all sections, in order of first appearance.
+    #[must_use]
+    pub fn stats(&self) -> Vec<SectionStats> {
+        self.section_order
+            .iter()
+            .filter_map(|name| self.sections.get(name).map(Self::summarize))
+            .collect()
+    }
+
+    /// Statistics of the section with the full name, e.g. `frame/render`.
+    #[must_use]
+    pub fn section_stats(&self, name: &str) -> Option<SectionStats> {
+        self.sections.get(name).map(Self::summarize)
+    }
+
+    /// Histogram of the rolling window of a section as `(lower bound in ms, count)` of
+    /// `num_buckets` equally wide buckets between the minimum and maximum duration.
+    #[must_use]
+    pub fn histogram(&self, name: &str, num_buckets: usize) -> Option<Vec<(f32, usize)>> {
+        let section = self.sections.get(name)?;
+        if num_buckets == 0 {
+            return Some(Vec::new());
+        }
+        let min = section.window.iter().copied().fold(f32::INFINITY, f32::min) * 1000f32;
+        let max = section.window.iter().copied().fold(0f32, f32::max) * 1000f32;
+        #[allow(clippy::cast_precision_loss)]
+        let width = (max - min) / num_buckets as f32;
+        let mut counts = vec![0; num_buckets];
+        for delta in &section.window {
+            #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+            let bucket = if width > 0f32 {
+                ((delta * 1000f32 - min) / width) as usize
+            } else {
+                0
+            };
+            counts[bucket.min(num_buckets - 1)] += 1;
+        }
+        #[allow(clippy::cast_precision_loss)]
+        let histogram = counts
+            .into_iter()
+            .enumerate()
+            .map(|(index, count)| (min + width * index as f32, count))
+            .collect();
+        Some(histogram)
     }
 
     pub fn print(&self) {
         info!("Timings");
-        for key in &self.section_order {
-            let (name, avg_delta, avg_square_delta) = self.sections.get(key).unwrap();
-            let variance = (avg_square_delta - avg_delta.powf(2f32)) * 1000f32;
-            let avg_delta = avg_delta * 1000f32;
-            info!("  {name: <20} {avg_delta:>10.2}ms (s²: {variance:>10.2}ms)");
+        for stats in self.stats() {
+            let SectionStats {
+                mean,
+                std_dev,
+                p50,
+                p95,
+                p99,
+                max,
+                ..
+            } = stats;
+            let name = stats.name.rsplit('/').next().unwrap_or_default();
+            let name = format!("{}{name}", "  ".repeat(stats.depth));
+            info!(
+                "  {name: <20} {mean:>10.2}ms (σ: {std_dev:>8.2}ms) \
+                 p50 {p50:>8.2}ms p95 {p95:>8.2}ms p99 {p99:>8.2}ms max {max:>8.2}ms"
+            );
         }
     }
+
+    /// Statistics of all sections as CSV with a header row, durations in milliseconds.
+    #[must_use]
+    pub fn to_csv(&self) -> String {
+        let mut csv =
+            "section,depth,mean_ms,std_dev_ms,p50_ms,p95_ms,p99_ms,max_ms,samples\n".to_owned();
+        for stats in self.stats() {
+            let _ = writeln!(
+                csv,
+                "{},{},{},{},{},{},{},{},{}",
+                csv_field(&stats.name),
+                stats.depth,
+                stats.mean,
+                stats.std_dev,
+                stats.p50,
+                stats.p95,
+                stats.p99,
+                stats.max,
+                stats.samples
+            );
+        }
+        csv
+    }
+
+    /// Statistics of all sections as a JSON array, durations in milliseconds.
+    #[must_use]
+    pub fn to_json(&self) -> String {
+        let sections = self
+            .stats()
+            .into_iter()
+            .map(|stats| {
+                format!(
+                    "{{\"section\":{},\"depth\":{},\"mean_ms\":{},\"std_dev_ms\":{},\
+                     \"p50_ms\":{},\"p95_ms\":{},\"p99_ms\":{},\"max_ms\":{},\"samples\":{}}}",
+                    json_string(&stats.name),
+                    stats.depth,
+                    stats.mean,
+                    stats.std_dev,
+                    stats.p50,
+                    stats.p95,
+                    stats.p99,
+                    stats.max,
+                    stats.samples
+                )
+            })
+            .collect::<Vec<_>>();
+        format!("[{}]", sections.join(","))
+    }
+
+    /// The recorded sections in the Chrome trace-event format, which can be opened in
+    /// `chrome://tracing` or Perfetto. Nested sections show up as nested slices.
+    #[must_use]
+    pub fn to_chrome_trace(&self) -> String {
+        let events = self
+            .trace
+            .iter()
+            .map(|event| {
+                let name = event.name.rsplit('/').next().unwrap_or_default();
+                format!(
+                    "{{\"name\":{},\"cat\":\"timer\",\"ph\":\"X\",\"ts\":{},\"dur\":{},\
+                     \"pid\":0,\"tid\":0,\"args\":{{\"section\":{}}}}}",
+                    json_string(name),
+                    event.start.as_secs_f64() * 1e6,
+                    event.duration.as_secs_f64() * 1e6,
+                    json_string(&event.name)
+                )
+            })
+            .collect::<Vec<_>>();
+        format!(
+            "{{\"displayTimeUnit\":\"ms\",\"traceEvents\":[{}]}}",
+            events.join(",")
+        )
+    }
 }
@@ -16,3 +16,23 @@ pub fn mtime(path: &Path) -> VResult<FileTime> {
 pub fn mix(a: f32, b: f32, alpha: f32) -> f32 {
     a * alpha + b * (1f32 - alpha)
 }
+
+#[must_use]
+pub fn gcd(a: usize, b: usize) -> usize {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+/// Least common multiple, used to find the period after which multi-buffered resources with
+/// different instance counts align again.
+#[must_use]
+pub fn lcm(a: usize, b: usize) -> usize {
+    if a == 0 || b == 0 {
+        0
+    } else {
+        a / gcd(a, b) * b
+    }
+}
@@ -0,0 +1,596 @@
+use std::{
+    ffi::{c_void, CString},
+    fmt::Display,
+    ptr,
+};
+
+use ash::vk;
+use winit::event::VirtualKeyCode;
+
+use super::{multi_image::SizePolicy, resources::physical_device::DeviceSelector};
+
+/// Device features which can be requested via `VulkanConfig::feature`. The names correspond to
+/// the fields of `VkPhysicalDeviceFeatures` and the `VkPhysicalDeviceVulkan1XFeatures` structs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceFeature {
+    // Vulkan 1.0.
+    ShaderFloat64,
+    ShaderInt64,
+    ShaderInt16,
+    ShaderStorageImageReadWithoutFormat,
+    ShaderStorageImageWriteWithoutFormat,
+    PipelineStatisticsQuery,
+
+    // Vulkan 1.1.
+    StorageBuffer16BitAccess,
+    UniformAndStorageBuffer16BitAccess,
+
+    // Vulkan 1.2.
+    ShaderFloat16,
+    ShaderInt8,
+    StorageBuffer8BitAccess,
+    UniformAndStorageBuffer8BitAccess,
+    ShaderBufferInt64Atomics,
+    ShaderSharedInt64Atomics,
+    ScalarBlockLayout,
+    BufferDeviceAddress,
+    TimelineSemaphore,
+    HostQueryReset,
+    VulkanMemoryModel,
+    RuntimeDescriptorArray,
+    DescriptorBindingPartiallyBound,
+    ShaderSampledImageArrayNonUniformIndexing,
+    ShaderStorageImageArrayNonUniformIndexing,
+    DescriptorBindingVariableDescriptorCount,
+    DescriptorBindingSampledImageUpdateAfterBind,
+    DescriptorBindingStorageImageUpdateAfterBind,
+
+    // Vulkan 1.3.
+    Synchronization2,
+    Maintenance4,
+    SubgroupSizeControl,
+    ComputeFullSubgroups,
+    ShaderIntegerDotProduct,
+}
+
+/// Features required for descriptor arrays and bindless tables. Enabled whenever supported.
+pub const DESCRIPTOR_INDEXING_FEATURES: [DeviceFeature; 4] = [
+    DeviceFeature::RuntimeDescriptorArray,
+    DeviceFeature::DescriptorBindingPartiallyBound,
+    DeviceFeature::ShaderSampledImageArrayNonUniformIndexing,
+    DeviceFeature::ShaderStorageImageArrayNonUniformIndexing,
+];
+
+/// Features required to size runtime arrays by the number of bound images, up to the
+/// update-after-bind limits. Enabled whenever supported.
+pub const VARIABLE_DESCRIPTOR_COUNT_FEATURES: [DeviceFeature; 3] = [
+    DeviceFeature::DescriptorBindingVariableDescriptorCount,
+    DeviceFeature::DescriptorBindingSampledImageUpdateAfterBind,
+    DeviceFeature::DescriptorBindingStorageImageUpdateAfterBind,
+];
+
+impl DeviceFeature {
+    /// The API version in which the feature struct containing this feature was introduced.
+    #[must_use]
+    pub fn api_version(self) -> u32 {
+        use DeviceFeature as F;
+        match self {
+            F::ShaderFloat64
+            | F::ShaderInt64
+            | F::ShaderInt16
+            | F::ShaderStorageImageReadWithoutFormat
+            | F::ShaderStorageImageWriteWithoutFormat
+            | F::PipelineStatisticsQuery => vk::API_VERSION_1_0,
+            // `VkPhysicalDeviceVulkan11Features` is part of Vulkan 1.2.
+            F::StorageBuffer16BitAccess
+            | F::UniformAndStorageBuffer16BitAccess
+            | F::ShaderFloat16
+            | F::ShaderInt8
+            | F::StorageBuffer8BitAccess
+            | F::UniformAndStorageBuffer8BitAccess
+            | F::ShaderBufferInt64Atomics
+            | F::ShaderSharedInt64Atomics
+            | F::ScalarBlockLayout
+            | F::BufferDeviceAddress
+            | F::TimelineSemaphore
+            | F::HostQueryReset
+            | F::VulkanMemoryModel
+            | F::RuntimeDescriptorArray
+            | F::DescriptorBindingPartiallyBound
+            | F::ShaderSampledImageArrayNonUniformIndexing
+            | F::ShaderStorageImageArrayNonUniformIndexing
+            | F::DescriptorBindingVariableDescriptorCount
+            | F::DescriptorBindingSampledImageUpdateAfterBind
+            | F::DescriptorBindingStorageImageUpdateAfterBind => vk::API_VERSION_1_2,
+            F::Synchronization2
+            | F::Maintenance4
+            | F::SubgroupSizeControl
+            | F::ComputeFullSubgroups
+            | F::ShaderIntegerDotProduct => vk::API_VERSION_1_3,
+        }
+    }
+
+    fn field(self, features: &mut DeviceFeatures) -> &mut vk::Bool32 {
+        use DeviceFeature as F;
+        let DeviceFeatures {
+            core,
+            vulkan_11,
+            vulkan_12,
+            vulkan_13,
+        } = features;
+        match self {
+            F::ShaderFloat64 => &mut core.shader_float64,
+            F::ShaderInt64 => &mut core.shader_int64,
+            F::ShaderInt16 => &mut core.shader_int16,
+            F::ShaderStorageImageReadWithoutFormat => {
+                &mut core.shader_storage_image_read_without_format
+            }
+            F::ShaderStorageImageWriteWithoutFormat => {
+                &mut core.shader_storage_image_write_without_format
+            }
+            F::PipelineStatisticsQuery => &mut core.pipeline_statistics_query,
+            F::StorageBuffer16BitAccess => &mut vulkan_11.storage_buffer16_bit_access,
+            F::UniformAndStorageBuffer16BitAccess => {
+                &mut vulkan_11.uniform_and_storage_buffer16_bit_access
+            }
+            F::ShaderFloat16 => &mut vulkan_12.shader_float16,
+            F::ShaderInt8 => &mut vulkan_12.shader_int8,
+            F::StorageBuffer8BitAccess => &mut vulkan_12.storage_buffer8_bit_access,
+            F::UniformAndStorageBuffer8BitAccess => {
+                &mut vulkan_12.uniform_and_storage_buffer8_bit_access
+            }
+            F::ShaderBufferInt64Atomics => &mut vulkan_12.shader_buffer_int64_atomics,
+            F::ShaderSharedInt64Atomics => &mut vulkan_12.shader_shared_int64_atomics,
+            F::ScalarBlockLayout => &mut vulkan_12.scalar_block_layout,
+            F::BufferDeviceAddress => &mut vulkan_12.buffer_device_address,
+            F::TimelineSemaphore => &mut vulkan_12.timeline_semaphore,
+            F::HostQueryReset => &mut vulkan_12.host_query_reset,
+            F::VulkanMemoryModel => &mut vulkan_12.vulkan_memory_model,
+            F::RuntimeDescriptorArray => &mut vulkan_12.runtime_descriptor_array,
+            F::DescriptorBindingPartiallyBound => &mut vulkan_12.descriptor_binding_partially_bound,
+            F::ShaderSampledImageArrayNonUniformIndexing => {
+                &mut vulkan_12.shader_sampled_image_array_non_uniform_indexing
+            }
+            F::ShaderStorageImageArrayNonUniformIndexing => {
+                &mut vulkan_12.shader_storage_image_array_non_uniform_indexing
+            }
+            F::DescriptorBindingVariableDescriptorCount => {
+                &mut vulkan_12.descriptor_binding_variable_descriptor_count
+            }
+            F::DescriptorBindingSampledImageUpdateAfterBind => {
+                &mut vulkan_12.descriptor_binding_sampled_image_update_after_bind
+            }
+            F::DescriptorBindingStorageImageUpdateAfterBind => {
+                &mut vulkan_12.descriptor_binding_storage_image_update_after_bind
+            }
+            F::Synchronization2 => &mut vulkan_13.synchronization2,
+            F::Maintenance4 => &mut vulkan_13.maintenance4,
+            F::SubgroupSizeControl => &mut vulkan_13.subgroup_size_control,
+            F::ComputeFullSubgroups => &mut vulkan_13.compute_full_subgroups,
+            F::ShaderIntegerDotProduct => &mut vulkan_13.shader_integer_dot_product,
+        }
+    }
+}
+
+impl Display for DeviceFeature {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Vulkan spells features in camel case, e.g. `shaderFloat64`.
+        let name = format!("{self:?}");
+        let mut chars = name.chars();
+        if let Some(first) = chars.next() {
+            write!(f, "{}{}", first.to_ascii_lowercase(), chars.as_str())?;
+        }
+        Ok(())
+    }
+}
+
+/// The feature structs of all supported API versions.
+#[derive(Default, Clone, Copy)]
+pub struct DeviceFeatures {
+    pub core: vk::PhysicalDeviceFeatures,
+    pub vulkan_11: vk::PhysicalDeviceVulkan11Features,
+    pub vulkan_12: vk::PhysicalDeviceVulkan12Features,
+    pub vulkan_13: vk::PhysicalDeviceVulkan13Features,
+}
+
+impl DeviceFeatures {
+    #[must_use]
+    pub fn contains(&self, feature: DeviceFeature) -> bool {
+        let mut features = *self;
+        *feature.field(&mut features) == vk::TRUE
+    }
+
+    pub fn insert(&mut self, feature: DeviceFeature) {
+        *feature.field(self) = vk::TRUE;
+    }
+
+    /// Link the feature structs available in `api_version` into a chain. The returned struct
+    /// points into `self`, which must not move while the chain is in use. Call `unchain` before
+    /// moving `self`.
+    pub fn chain(&mut self, api_version: u32) -> vk::PhysicalDeviceFeatures2 {
+        let mut next: *mut c_void = ptr::null_mut();
+        if api_version >= vk::API_VERSION_1_3 {
+            self.vulkan_13.p_next = next;
+            next = ptr::addr_of_mut!(self.vulkan_13).cast();
+        }
+        if api_version >= vk::API_VERSION_1_2 {
+            self.vulkan_12.p_next = next;
+            next = ptr::addr_of_mut!(self.vulkan_12).cast();
+            self.vulkan_11.p_next = next;
+            next = ptr::addr_of_mut!(self.vulkan_11).cast();
+        }
+        vk::PhysicalDeviceFeatures2 {
+            p_next: next,
+            features: self.core,
+            ..Default::default()
+        }
+    }
+
+    /// Clear the pointers set by `chain`, so no dangling pointers remain once `self` moves.
+    pub fn unchain(&mut self) {
+        self.vulkan_11.p_next = ptr::null_mut();
+        self.vulkan_12.p_next = ptr::null_mut();
+        self.vulkan_13.p_next = ptr::null_mut();
+    }
+}
+
+/// Color space of the presented image. Shaders receive it as the built-in `color_space` push
+/// constant (`0` sRGB, `1` scRGB, `2` HDR10) and have to encode their output accordingly, the
+/// swapchain formats are never sRGB encoded by the hardware.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorSpace {
+    /// Non-linear sRGB, shaders apply the sRGB transfer function.
+    Srgb = 0,
+    /// Linear extended sRGB, values outside of `[0, 1]` are valid. Needs a float format.
+    ExtendedSrgbLinear = 1,
+    /// BT.2020 primaries with the ST 2084 (PQ) transfer function.
+    Hdr10 = 2,
+}
+
+impl ColorSpace {
+    #[must_use]
+    pub fn vk_color_space(self) -> vk::ColorSpaceKHR {
+        match self {
+            Self::Srgb => vk::ColorSpaceKHR::SRGB_NONLINEAR,
+            Self::ExtendedSrgbLinear => vk::ColorSpaceKHR::EXTENDED_SRGB_LINEAR_EXT,
+            Self::Hdr10 => vk::ColorSpaceKHR::HDR10_ST2084_EXT,
+        }
+    }
+
+    #[must_use]
+    pub fn from_vk_color_space(color_space: vk::ColorSpaceKHR) -> Option<Self> {
+        [Self::Srgb, Self::ExtendedSrgbLinear, Self::Hdr10]
+            .into_iter()
+            .find(|candidate| candidate.vk_color_space() == color_space)
+    }
+}
+
+/// Swapchain formats tried in order. All of them are unorm or float formats which may support
+/// storage usage, sRGB formats usually don't. BGR ordered formats are only used with
+/// `PresentStrategy::Blit`, as shaders can't declare their format qualifier.
+pub const DEFAULT_SURFACE_FORMATS: [vk::Format; 5] = [
+    vk::Format::R8G8B8A8_UNORM,
+    vk::Format::B8G8R8A8_UNORM,
+    vk::Format::A2B10G10R10_UNORM_PACK32,
+    vk::Format::A2R10G10B10_UNORM_PACK32,
+    vk::Format::R16G16B16A16_SFLOAT,
+];
+
+/// How shader output reaches the swapchain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PresentStrategy {
+    /// Use `Direct` if the surface supports storage images, `Blit` otherwise.
+    Auto,
+    /// Shaders write to the swapchain images, which requires storage support.
+    Direct,
+    /// Shaders write to an internal image, which is blitted to the swapchain at the end of the
+    /// frame.
+    Blit,
+}
+
+/// Swapchain present mode. Unsupported modes fall back along `PresentMode::fallbacks`, `Fifo`
+/// is always available.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PresentMode {
+    /// Vsync, frames are queued.
+    Fifo,
+    /// Vsync, but late frames are presented immediately and may tear.
+    FifoRelaxed,
+    /// No tearing, the newest frame replaces queued ones.
+    Mailbox,
+    /// No vsync, may tear.
+    Immediate,
+}
+
+impl PresentMode {
+    pub const ALL: [Self; 4] = [
+        Self::Fifo,
+        Self::FifoRelaxed,
+        Self::Mailbox,
+        Self::Immediate,
+    ];
+
+    #[must_use]
+    pub fn vk_present_mode(self) -> vk::PresentModeKHR {
+        match self {
+            Self::Fifo => vk::PresentModeKHR::FIFO,
+            Self::FifoRelaxed => vk::PresentModeKHR::FIFO_RELAXED,
+            Self::Mailbox => vk::PresentModeKHR::MAILBOX,
+            Self::Immediate => vk::PresentModeKHR::IMMEDIATE,
+        }
+    }
+
+    /// This mode followed by the modes to try if it is unsupported, in order.
+    #[must_use]
+    pub fn fallbacks(self) -> &'static [Self] {
+        match self {
+            Self::Fifo => &[Self::Fifo],
+            Self::FifoRelaxed => &[Self::FifoRelaxed, Self::Fifo],
+            Self::Mailbox => &[
+                Self::Mailbox,
+                Self::Immediate,
+                Self::FifoRelaxed,
+                Self::Fifo,
+            ],
+            Self::Immediate => &[
+                Self::Immediate,
+                Self::Mailbox,
+                Self::FifoRelaxed,
+                Self::Fifo,
+            ],
+        }
+    }
+}
+
+/// Filter used to upscale the render resolution to the window.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpscaleFilter {
+    /// Blocky, for pixel art.
+    Nearest,
+    /// Needs linear filtering support of the intermediate format, otherwise falls back to
+    /// `Nearest`.
+    Linear,
+    /// Edge-adaptive upscaling and sharpening in two compute passes, similar to AMD FSR 1
+    /// (EASU and RCAS). Blits the upscaled `rgba32f` image, see `VulkanConfig::fsr_sharpness`.
+    Fsr,
+}
+
+impl UpscaleFilter {
+    #[must_use]
+    pub fn vk_filter(self) -> vk::Filter {
+        match self {
+            Self::Nearest => vk::Filter::NEAREST,
+            Self::Linear => vk::Filter::LINEAR,
+            // The FSR output already has the window resolution.
+            Self::Fsr => vk::Filter::NEAREST,
+        }
+    }
+}
+
+/// Instance and device creation parameters.
+///
+/// ```ignore
+/// let config = VulkanConfig::default()
+///     .validation(false)
+///     .feature(DeviceFeature::ShaderFloat64)
+///     .device_extension("VK_EXT_shader_atomic_float");
+/// let vulkan = Vulkan::with_config(&window, &shader_paths, &config)?;
+/// ```
+#[derive(Clone)]
+pub struct VulkanConfig {
+    pub validation: bool,
+    pub debug_utils: bool,
+    pub panic_on_validation_error: bool,
+    pub api_version: u32,
+    pub present_mode: PresentMode,
+    pub device: DeviceSelector,
+    pub instance_extensions: Vec<CString>,
+    pub device_extensions: Vec<CString>,
+    pub features: Vec<DeviceFeature>,
+    pub surface_formats: Vec<vk::Format>,
+    pub color_space: ColorSpace,
+    pub present_strategy: PresentStrategy,
+    pub intermediate_format: vk::Format,
+    pub render_resolution: SizePolicy,
+    pub upscale_filter: UpscaleFilter,
+    pub fsr_sharpness: f32,
+    pub gpu_timing: bool,
+    pub gpu_timing_alpha: f32,
+    pub pipeline_statistics: bool,
+    pub transparent: bool,
+    pub overlay: bool,
+    pub overlay_key: Option<VirtualKeyCode>,
+}
+
+impl Default for VulkanConfig {
+    fn default() -> Self {
+        Self {
+            validation: cfg!(debug_assertions),
+            debug_utils: true,
+            panic_on_validation_error: false,
+            api_version: vk::API_VERSION_1_3,
+            present_mode: PresentMode::Fifo,
+            device: DeviceSelector::Auto,
+            instance_extensions: Vec::new(),
+            device_extensions: Vec::new(),
+            features: Vec::new(),
+            surface_formats: DEFAULT_SURFACE_FORMATS.to_vec(),
+            color_space: ColorSpace::Srgb,
+            present_strategy: PresentStrategy::Auto,
+            intermediate_format: vk::Format::R32G32B32A32_SFLOAT,
+            render_resolution: SizePolicy::Screen,
+            upscale_filter: UpscaleFilter::Linear,
+            fsr_sharpness: 0.2,
+            gpu_timing: true,
+            gpu_timing_alpha: 0.95,
+            pipeline_statistics: true,
+            transparent: false,
+            overlay: false,
+            overlay_key: Some(VirtualKeyCode::F3),
+        }
+    }
+}
+
+impl VulkanConfig {
+    /// Enable `VK_LAYER_KHRONOS_validation`, if installed. Defaults to on in debug builds.
+    #[must_use]
+    pub fn validation(mut self, validation: bool) -> Self {
+        self.validation = validation;
+        self
+    }
+
+    /// Route validation messages through `log` and label objects for debuggers, using
+    /// `VK_EXT_debug_utils` if available. Defaults to on.
+    #[must_use]
+    pub fn debug_utils(mut self, debug_utils: bool) -> Self {
+        self.debug_utils = debug_utils;
+        self
+    }
+
+    /// Panic at the end of the frame if a validation error was reported. Meant for tests.
+    #[must_use]
+    pub fn panic_on_validation_error(mut self, panic_on_validation_error: bool) -> Self {
+        self.panic_on_validation_error = panic_on_validation_error;
+        self
+    }
+
+    #[must_use]
+    pub fn api_version(mut self, major: u32, minor: u32) -> Self {
+        self.api_version = vk::make_api_version(0, major, minor, 0);
+        self
+    }
+
+    /// Can be changed later using `Vulkan::set_present_mode`.
+    #[must_use]
+    pub fn present_mode(mut self, present_mode: PresentMode) -> Self {
+        self.present_mode = present_mode;
+        self
+    }
+
+    /// Choose the physical device. The `SHADE_DEVICE` environment variable takes precedence.
+    #[must_use]
+    pub fn device(mut self, device: DeviceSelector) -> Self {
+        self.device = device;
+        self
+    }
+
+    #[must_use]
+    pub fn instance_extension(mut self, name: &str) -> Self {
+        self.instance_extensions
+            .push(CString::new(name).expect("Extension names must not contain NUL"));
+        self
+    }
+
+    #[must_use]
+    pub fn device_extension(mut self, name: &str) -> Self {
+        self.device_extensions
+            .push(CString::new(name).expect("Extension names must not contain NUL"));
+        self
+    }
+
+    /// Require a device feature. Device creation fails if the feature is not supported.
+    #[must_use]
+    pub fn feature(mut self, feature: DeviceFeature) -> Self {
+        self.features.push(feature);
+        self
+    }
+
+    /// Swapchain formats in order of preference. Formats which can't be written by the present
+    /// strategy are skipped.
+    #[must_use]
+    pub fn surface_formats(mut self, surface_formats: &[vk::Format]) -> Self {
+        self.surface_formats = surface_formats.to_vec();
+        self
+    }
+
+    /// Request a color space, falls back to sRGB if the surface doesn't support it. Anything but
+    /// sRGB needs `VK_EXT_swapchain_colorspace`, which is enabled automatically.
+    #[must_use]
+    pub fn color_space(mut self, color_space: ColorSpace) -> Self {
+        self.color_space = color_space;
+        self
+    }
+
+    #[must_use]
+    pub fn present_strategy(mut self, present_strategy: PresentStrategy) -> Self {
+        self.present_strategy = present_strategy;
+        self
+    }
+
+    /// Format of the internal `present` image used by `PresentStrategy::Blit`. Has to match the
+    /// format qualifier of the shaders' `present` declaration. Defaults to `rgba32f`.
+    #[must_use]
+    pub fn intermediate_format(mut self, intermediate_format: vk::Format) -> Self {
+        self.intermediate_format = intermediate_format;
+        self
+    }
+
+    /// Resolution shaders are dispatched at, relative to the window or fixed. Anything but the
+    /// window resolution renders to an intermediate image which is upscaled to the swapchain,
+    /// which requires `PresentStrategy::Auto` or `Blit`. Can be changed later using
+    /// `Vulkan::set_render_resolution`.
+    #[must_use]
+    pub fn render_resolution(mut self, render_resolution: SizePolicy) -> Self {
+        self.render_resolution = render_resolution;
+        self
+    }
+
+    #[must_use]
+    pub fn upscale_filter(mut self, upscale_filter: UpscaleFilter) -> Self {
+        self.upscale_filter = upscale_filter;
+        self
+    }
+
+    /// Sharpening of `UpscaleFilter::Fsr` in stops, 0 is the strongest. Defaults to 0.2.
+    #[must_use]
+    pub fn fsr_sharpness(mut self, fsr_sharpness: f32) -> Self {
+        self.fsr_sharpness = fsr_sharpness;
+        self
+    }
+
+    /// Write timestamps around each shader pass, see `Vulkan::gpu_timings`. Defaults to on,
+    /// ignored if the compute queue doesn't support timestamps.
+    #[must_use]
+    pub fn gpu_timing(mut self, gpu_timing: bool) -> Self {
+        self.gpu_timing = gpu_timing;
+        self
+    }
+
+    /// Smoothing factor of the GPU timings, as in `Timer::new`.
+    #[must_use]
+    pub fn gpu_timing_alpha(mut self, gpu_timing_alpha: f32) -> Self {
+        self.gpu_timing_alpha = gpu_timing_alpha;
+        self
+    }
+
+    /// Count compute shader invocations of each shader pass, see `Vulkan::pipeline_statistics`.
+    /// Defaults to on, ignored if `pipelineStatisticsQuery` is unsupported.
+    #[must_use]
+    pub fn pipeline_statistics(mut self, pipeline_statistics: bool) -> Self {
+        self.pipeline_statistics = pipeline_statistics;
+        self
+    }
+
+    /// Composite the presented images with the desktop using their alpha channel, if the surface
+    /// supports it. Enabled automatically for windows created with `WindowConfig::transparent`.
+    #[must_use]
+    pub fn transparent(mut self, transparent: bool) -> Self {
+        self.transparent = transparent;
+        self
+    }
+
+    /// Start with the overlay showing the FPS, GPU timings and shader errors, see
+    /// `Vulkan::toggle_overlay`.
+    #[must_use]
+    pub fn overlay(mut self, overlay: bool) -> Self {
+        self.overlay = overlay;
+        self
+    }
+
+    /// Key toggling the overlay, handled if `Vulkan::set_input` was called. Defaults to F3.
+    #[must_use]
+    pub fn overlay_key(mut self, overlay_key: Option<VirtualKeyCode>) -> Self {
+        self.overlay_key = overlay_key;
+        self
+    }
+}
@@ -0,0 +1,297 @@
+use std::{collections::HashMap, path::Path, rc::Rc};
+
+use ash::vk;
+
+use crate::error::VResult;
+
+use super::{multi_image::MultiImage, ShaderResources, Value, Vulkan};
+
+/// Format of the upscaled images, matching the `rgba32f` qualifier of the passes.
+pub(super) const FORMAT: vk::Format = vk::Format::R32G32B32A32_SFLOAT;
+
+const LOCAL_SIZE: u32 = 8;
+
+/// Edge-adaptive upscaling from `INPUT`, the present image at render resolution, to `OUTPUT` at
+/// window resolution. Follows the edge-aligned Lanczos approximation of FSR 1 EASU, using the 12
+/// nearest texels and clamping the result to the 2x2 texels around the sample to avoid ringing.
+const EASU_SHADER: &str = r"#version 450
+
+layout(local_size_x = 8, local_size_y = 8) in;
+
+layout(binding = 0) uniform sampler2D INPUT;
+layout(rgba32f, binding = 1) uniform image2D OUTPUT;
+
+vec4 fetch(ivec2 texel) {
+    return texelFetch(INPUT, clamp(texel, ivec2(0), textureSize(INPUT, 0) - 1), 0);
+}
+
+float luma(vec4 color) {
+    return dot(color.rgb, vec3(0.5, 1.0, 0.5));
+}
+
+// Approximated Lanczos2 weight of a tap at `offset` from the sample, rotated to the edge
+// direction and stretched along the edge.
+float weight(vec2 offset, vec2 direction, vec2 stretch, float lobe, float clip) {
+    vec2 rotated = vec2(dot(offset, direction), dot(offset, vec2(-direction.y, direction.x)));
+    rotated *= stretch;
+    float distance2 = min(dot(rotated, rotated), clip);
+    float base = 2.0 / 5.0 * distance2 - 1.0;
+    float window = lobe * distance2 - 1.0;
+    base *= base;
+    window *= window;
+    return (25.0 / 16.0 * base - (25.0 / 16.0 - 1.0)) * window;
+}
+
+void main() {
+    ivec2 pixel = ivec2(gl_GlobalInvocationID.xy);
+    ivec2 output_size = imageSize(OUTPUT);
+    if (any(greaterThanEqual(pixel, output_size))) {
+        return;
+    }
+    vec2 scale = vec2(textureSize(INPUT, 0)) / vec2(output_size);
+    vec2 position = (vec2(pixel) + 0.5) * scale - 0.5;
+    ivec2 base = ivec2(floor(position));
+    vec2 fraction = position - vec2(base);
+
+    // Edge direction and strength, bilinearly interpolated from the gradients of the 2x2
+    // texels around the sample.
+    vec2 direction = vec2(0.0);
+    float edge = 0.0;
+    for (int y = 0; y <= 1; y++) {
+        for (int x = 0; x <= 1; x++) {
+            ivec2 texel = base + ivec2(x, y);
+            vec2 bilinear = mix(1.0 - fraction, fraction, vec2(x, y));
+            float w = bilinear.x * bilinear.y;
+            float left = luma(fetch(texel - ivec2(1, 0)));
+            float right = luma(fetch(texel + ivec2(1, 0)));
+            float up = luma(fetch(texel - ivec2(0, 1)));
+            float down = luma(fetch(texel + ivec2(0, 1)));
+            float center = luma(fetch(texel));
+            vec2 gradient = vec2(right - left, down - up);
+            direction += gradient * w;
+
+            // Large where the gradient is steady, small at isolated texels.
+            vec2 contrast = max(abs(vec2(right, down) - center), abs(center - vec2(left, up)));
+            vec2 steadiness = clamp(abs(gradient) / max(contrast, 1.0 / 4096.0), 0.0, 1.0);
+            edge += dot(steadiness * steadiness, vec2(0.5)) * w;
+        }
+    }
+    float length2 = dot(direction, direction);
+    direction = length2 < 1.0 / 32768.0 ? vec2(1.0, 0.0) : direction * inversesqrt(length2);
+    edge *= edge;
+
+    float axis_stretch = 1.0 / max(abs(direction.x), abs(direction.y));
+    vec2 stretch = vec2(1.0 + (axis_stretch - 1.0) * edge, 1.0 - 0.5 * edge);
+    float lobe = 0.5 + ((1.0 / 4.0 - 0.04) - 0.5) * edge;
+    float clip = 1.0 / lobe;
+
+    vec4 color = vec4(0.0);
+    float total = 0.0;
+    vec4 minimum = vec4(1e30);
+    vec4 maximum = vec4(-1e30);
+    for (int y = -1; y <= 2; y++) {
+        for (int x = -1; x <= 2; x++) {
+            // Skip the corners of the 4x4 neighborhood.
+            if ((x == -1 || x == 2) && (y == -1 || y == 2)) {
+                continue;
+            }
+            vec4 tap = fetch(base + ivec2(x, y));
+            float w = weight(vec2(x, y) - fraction, direction, stretch, lobe, clip);
+            color += tap * w;
+            total += w;
+            if (x >= 0 && x <= 1 && y >= 0 && y <= 1) {
+                minimum = min(minimum, tap);
+                maximum = max(maximum, tap);
+            }
+        }
+    }
+    imageStore(OUTPUT, pixel, clamp(color / total, minimum, maximum));
+}
+";
+
+/// Contrast adaptive sharpening of `INPUT` into `OUTPUT`, following FSR 1 RCAS. The sharpening
+/// lobe is limited so the result stays within the range of the cross-shaped neighborhood.
+const RCAS_SHADER: &str = r"#version 450
+
+layout(local_size_x = 8, local_size_y = 8) in;
+
+layout(push_constant, std140) uniform PushConstants {
+    layout(offset = 0) float fsr_sharpness;
+} constants;
+
+layout(binding = 0) uniform sampler2D INPUT;
+layout(rgba32f, binding = 1) uniform image2D OUTPUT;
+
+const float LOBE_LIMIT = 0.25 - 1.0 / 16.0;
+
+void main() {
+    ivec2 pixel = ivec2(gl_GlobalInvocationID.xy);
+    ivec2 size = imageSize(OUTPUT);
+    if (any(greaterThanEqual(pixel, size))) {
+        return;
+    }
+
+    vec4 center = texelFetch(INPUT, pixel, 0);
+    vec3 up = texelFetch(INPUT, max(pixel - ivec2(0, 1), ivec2(0)), 0).rgb;
+    vec3 left = texelFetch(INPUT, max(pixel - ivec2(1, 0), ivec2(0)), 0).rgb;
+    vec3 right = texelFetch(INPUT, min(pixel + ivec2(1, 0), size - 1), 0).rgb;
+    vec3 down = texelFetch(INPUT, min(pixel + ivec2(0, 1), size - 1), 0).rgb;
+
+    // The largest negative lobe which neither clips below 0 nor above 1.
+    vec3 minimum = min(min(up, left), min(right, down));
+    vec3 maximum = max(max(up, left), max(right, down));
+    vec3 hit_min = minimum / max(4.0 * maximum, 1.0 / 4096.0);
+    vec3 hit_max = (1.0 - maximum) / min(4.0 * minimum - 4.0, -1.0 / 4096.0);
+    vec3 lobes = max(-hit_min, hit_max);
+    float lobe = max(-LOBE_LIMIT, min(max(lobes.r, max(lobes.g, lobes.b)), 0.0));
+    lobe *= exp2(-constants.fsr_sharpness);
+
+    vec3 color = (lobe * (up + left + right + down) + center.rgb) / (4.0 * lobe + 1.0);
+    imageStore(OUTPUT, pixel, vec4(color, center.a));
+}
+";
+
+/// The upscaling passes of a window using `UpscaleFilter::Fsr`, with their output images.
+pub(super) struct Fsr {
+    easu: ShaderResources,
+    rcas: ShaderResources,
+    /// The output of both passes, the latter is blitted to the swapchain without scaling.
+    images: [Rc<MultiImage>; 2],
+}
+
+impl Fsr {
+    pub(super) fn upscaled_image(&self) -> &MultiImage {
+        &self.images[1]
+    }
+
+    pub(super) fn images(&self) -> &[Rc<MultiImage>] {
+        &self.images
+    }
+
+    pub(super) fn invalidate_association_cache(&mut self) {
+        self.easu.invalidate_association_cache();
+        self.rcas.invalidate_association_cache();
+    }
+}
+
+/// Names of the images the passes of present image `present_name` write to.
+pub(super) fn image_names(present_name: &str) -> [String; 2] {
+    [
+        format!("{present_name}_easu"),
+        format!("{present_name}_upscaled"),
+    ]
+}
+
+impl Vulkan {
+    /// (Re)create the output images of the upscaling passes of window `target` at its surface
+    /// resolution. The shaders are only compiled once.
+    pub(super) unsafe fn reinitialize_fsr(&mut self, target: usize) -> VResult<()> {
+        let window = &mut self.targets[target];
+        let present_name = window.present_name.clone();
+        let surface_resolution = window.surface_info.surface_resolution;
+        let shaders = window.fsr.take().map(|fsr| (fsr.easu, fsr.rcas));
+
+        // Every frame waits for the previous one of the window, so one instance suffices.
+        let [easu_name, upscaled_name] = image_names(&present_name);
+        let easu_image =
+            self.create_multi_image(&easu_name, FORMAT, surface_resolution, Some(1))?;
+        let upscaled_image =
+            self.create_multi_image(&upscaled_name, FORMAT, surface_resolution, Some(1))?;
+
+        let (easu, rcas) = if let Some(shaders) = shaders {
+            shaders
+        } else {
+            let easu_source = EASU_SHADER
+                .replace("INPUT", &present_name)
+                .replace("OUTPUT", &easu_name);
+            let rcas_source = RCAS_SHADER
+                .replace("INPUT", &easu_name)
+                .replace("OUTPUT", &upscaled_name);
+            (
+                ShaderResources::from_source(
+                    &self.device,
+                    Path::new("fsr_easu.comp"),
+                    &easu_source,
+                    &present_name,
+                )?,
+                ShaderResources::from_source(
+                    &self.device,
+                    Path::new("fsr_rcas.comp"),
+                    &rcas_source,
+                    &present_name,
+                )?,
+            )
+        };
+
+        self.targets[target].fsr = Some(Fsr {
+            easu,
+            rcas,
+            images: [easu_image, upscaled_image],
+        });
+        Ok(())
+    }
+
+    /// Upscale and sharpen the output of the shaders of window `target`, before it is blitted.
+    pub(super) unsafe fn record_fsr(
+        &mut self,
+        target: usize,
+        command_buffer: vk::CommandBuffer,
+        present_index: usize,
+    ) -> VResult<()> {
+        let window = &self.targets[target];
+        let surface_resolution = window.surface_info.surface_resolution;
+        let constants = HashMap::from([(
+            "fsr_sharpness".to_owned(),
+            Value::F32(window.config.fsr_sharpness),
+        )]);
+
+        for pass in 0..2 {
+            let window = &mut self.targets[target];
+            let Some(fsr) = &mut window.fsr else {
+                return Ok(());
+            };
+            let resources = if pass == 0 {
+                &mut fsr.easu
+            } else {
+                &mut fsr.rcas
+            };
+            let (write_descriptor_set, descriptor_sets) = resources.get_descriptor_sets(
+                &self.device,
+                &self.available_images,
+                &self.available_buffers,
+                &window.present_name,
+                present_index,
+                self.num_frames,
+            )?;
+            let Some(fsr) = &self.targets[target].fsr else {
+                return Ok(());
+            };
+            let resources = if pass == 0 { &fsr.easu } else { &fsr.rcas };
+
+            // Read the output of the previous pass.
+            self.shader_write_barrier(command_buffer);
+            self.bind_pipeline(command_buffer, &resources.pipeline);
+            self.push_constants(
+                command_buffer,
+                &resources.pipeline_layout,
+                &resources.shader_module,
+                &constants,
+            );
+            self.push_descriptors(
+                command_buffer,
+                &resources.pipeline_layout,
+                resources.descriptors.push_set(),
+                &write_descriptor_set,
+            );
+            self.bind_descriptor_sets(command_buffer, &resources.pipeline_layout, &descriptor_sets);
+            // Cover the whole window, the shaders skip pixels outside.
+            self.device.cmd_dispatch(
+                command_buffer,
+                (surface_resolution.width + LOCAL_SIZE - 1) / LOCAL_SIZE,
+                (surface_resolution.height + LOCAL_SIZE - 1) / LOCAL_SIZE,
+                1,
+            );
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,148 @@
+use std::{collections::HashMap, rc::Rc};
+
+use ash::vk;
+use log::{info, warn};
+
+use crate::{error::VResult, utils::mix};
+
+use super::{
+    pass_queries::PassQueries,
+    resources::{device::Device, physical_device::PhysicalDevice},
+};
+
+/// Smoothed GPU durations of each shader pass, measured with timestamp queries. Passes are not
+/// separated by barriers, so consecutive passes may overlap.
+pub struct GpuTimer {
+    alpha: f32,
+    /// A start and end timestamp per pass, named by shader path.
+    queries: Option<PassQueries<String>>,
+    /// Nanoseconds per timestamp tick.
+    timestamp_period: f32,
+    valid_mask: u64,
+    section_order: Vec<String>,
+    sections: HashMap<String, (f32, f32)>,
+}
+
+impl GpuTimer {
+    pub(super) unsafe fn new(
+        device: &Rc<Device>,
+        physical_device: &PhysicalDevice,
+        enabled: bool,
+        alpha: f32,
+        num_passes: usize,
+    ) -> VResult<Self> {
+        let valid_bits = physical_device.info.timestamp_valid_bits;
+        let timestamp_period = physical_device.info.limits().timestamp_period;
+        let supported = valid_bits > 0 && timestamp_period > 0f32;
+        if enabled && !supported {
+            warn!("Compute queue doesn't support timestamps, GPU timings are unavailable");
+        }
+        let queries = if enabled && supported && num_passes > 0 {
+            Some(PassQueries::new(
+                device,
+                vk::QueryType::TIMESTAMP,
+                vk::QueryPipelineStatisticFlags::empty(),
+                num_passes,
+            )?)
+        } else {
+            None
+        };
+        let valid_mask = if valid_bits >= 64 {
+            u64::MAX
+        } else {
+            (1u64 << valid_bits) - 1
+        };
+        Ok(Self {
+            alpha,
+            queries,
+            timestamp_period,
+            valid_mask,
+            section_order: Vec::new(),
+            sections: HashMap::new(),
+        })
+    }
+
+    /// Fold the pass durations of the last submitted frame into the averages, once its fence
+    /// signaled.
+    pub(super) unsafe fn collect(&mut self) -> VResult<()> {
+        let Some(queries) = &mut self.queries else {
+            return Ok(());
+        };
+        let Some((names, timestamps)) = queries.take_results()? else {
+            return Ok(());
+        };
+        for (name, pair) in names.into_iter().zip(timestamps.chunks_exact(2)) {
+            let ticks = pair[1].wrapping_sub(pair[0]) & self.valid_mask;
+            #[allow(clippy::cast_precision_loss)]
+            let delta = ticks as f32 * self.timestamp_period / 1e9f32;
+            match self.sections.get_mut(&name) {
+                None => {
+                    self.sections
+                        .insert(name.clone(), (delta, delta.powf(2f32)));
+                    self.section_order.push(name);
+                }
+                Some((avg_delta, avg_square_delta)) => {
+                    *avg_delta = mix(*avg_delta, delta, self.alpha);
+                    *avg_square_delta = mix(*avg_square_delta, delta.powf(2f32), self.alpha);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Clear the timestamps of the previous frame before the first pass writes them.
+    pub(super) unsafe fn reset(&self, device: &Device, command_buffer: vk::CommandBuffer) {
+        if let Some(queries) = &self.queries {
+            queries.reset(device, command_buffer);
+        }
+    }
+
+    /// Write the start timestamp of pass `index` once all previous commands started.
+    pub(super) unsafe fn begin_pass(
+        &self,
+        device: &Device,
+        command_buffer: vk::CommandBuffer,
+        index: usize,
+    ) {
+        if let Some(queries) = &self.queries {
+            queries.begin(device, command_buffer, index);
+        }
+    }
+
+    /// Write the end timestamp of pass `index` once it completed, timed as `name`.
+    pub(super) unsafe fn end_pass(
+        &mut self,
+        device: &Device,
+        command_buffer: vk::CommandBuffer,
+        index: usize,
+        name: String,
+    ) {
+        if let Some(queries) = &mut self.queries {
+            queries.end(device, command_buffer, index, name);
+        }
+    }
+
+    #[must_use]
+    pub fn is_enabled(&self) -> bool {
+        self.queries.is_some()
+    }
+
+    /// Smoothed duration in milliseconds of each pass, in order of first appearance.
+    #[must_use]
+    pub fn timings(&self) -> Vec<(&str, f32)> {
+        self.section_order
+            .iter()
+            .map(|name| (name.as_str(), self.sections[name].0 * 1000f32))
+            .collect()
+    }
+
+    pub fn print(&self) {
+        info!("GPU timings");
+        for name in &self.section_order {
+            let (avg_delta, avg_square_delta) = self.sections[name];
+            let variance = (avg_square_delta - avg_delta.powf(2f32)) * 1000f32;
+            let avg_delta = avg_delta * 1000f32;
+            info!("  {name: <20} {avg_delta:>10.2}ms (s²: {variance:>10.2}ms)");
+        }
+    }
+}
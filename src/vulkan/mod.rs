@@ -1,4 +1,11 @@
-use std::{collections::HashMap, mem, ops::Deref, path::Path, rc::Rc};
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    mem,
+    ops::Deref,
+    path::Path,
+    rc::Rc,
+};
 
 use ash::{
     extensions::khr::{PushDescriptor, Surface as SurfaceLoader, Swapchain as SwapchainLoader},
@@ -6,27 +13,95 @@ use ash::{
 };
 use filetime::FileTime;
 use log::{debug, error, info, warn};
+use winit::{event_loop::EventLoopProxy, window::WindowId};
 
 use crate::{
+    cell,
     error::{Error, VResult},
+    input::InputState,
     utils::mtime,
     window::Window,
 };
 
+pub mod config;
+mod fsr;
+pub mod gpu_timer;
 pub mod multi_buffer;
 pub mod multi_image;
+pub mod overlay;
+mod pass_queries;
+pub mod pipeline_statistics;
 pub mod resources;
 
+use self::{
+    config::{PresentMode, PresentStrategy, UpscaleFilter, VulkanConfig},
+    fsr::Fsr,
+    gpu_timer::GpuTimer,
+    multi_buffer::MultiBuffer,
+    multi_image::{ManagedImage, MultiImage, SizePolicy},
+    overlay::Overlay,
+    pipeline_statistics::PipelineStatistics,
+};
+
 use self::resources::{
-    buffer::Buffer, command_buffer::CommandBuffer, command_pool::CommandPool,
-    descriptor_layout::DescriptorLayout, descriptors::Descriptors, device::Device, fence::Fence,
-    image::Image, image_view::ImageView, instance::Instance, physical_device::PhysicalDevice,
-    pipeline::Pipeline, pipeline_layout::PipelineLayout, sampler::Sampler, semaphore::Semaphore,
-    shader_module::ShaderModule, surface::Surface, surface_info::SurfaceInfo, swapchain::Swapchain,
+    buffer::Buffer,
+    command_buffer::CommandBuffer,
+    command_pool::CommandPool,
+    descriptor_layout::DescriptorLayout,
+    descriptors::{AllocatedDescriptorSets, Descriptors},
+    device::Device,
+    fence::Fence,
+    image::Image,
+    image_view::ImageView,
+    instance::Instance,
+    physical_device::{PhysicalDevice, PhysicalDeviceInfo},
+    pipeline::Pipeline,
+    pipeline_layout::PipelineLayout,
+    sampler::Sampler,
+    semaphore::Semaphore,
+    shader_module::ShaderModule,
+    surface::Surface,
+    surface_info::SurfaceInfo,
+    swapchain::Swapchain,
 };
 
+/// Set when the next `tick` changes the output, shared with the buffers. Wakes an on-demand
+/// `EventLoop` through its proxy, see `Vulkan::set_event_loop_proxy`.
+pub(crate) struct RedrawRequest {
+    requested: Cell<bool>,
+    proxy: RefCell<Option<EventLoopProxy<()>>>,
+}
+
+impl RedrawRequest {
+    fn new() -> Self {
+        Self {
+            requested: Cell::new(true),
+            proxy: RefCell::new(None),
+        }
+    }
+
+    pub(crate) fn request(&self) {
+        self.requested.set(true);
+        if let Some(proxy) = &*self.proxy.borrow() {
+            // Only fails once the event loop exited, when there is nothing left to wake.
+            let _ = proxy.send_event(());
+        }
+    }
+
+    fn clear(&self) {
+        self.requested.set(false);
+    }
+
+    fn is_requested(&self) -> bool {
+        self.requested.get()
+    }
+}
+
 pub enum Event {
-    Resized,
+    /// The swapchain was recreated with the given extent.
+    Resized(vk::Extent2D),
+    /// The swapchain of a window added with `Vulkan::add_window` was recreated.
+    WindowResized(WindowId, vk::Extent2D),
 }
 
 #[derive(Clone)]
@@ -34,25 +109,58 @@ pub enum Value {
     F32(f32),
     U32(u32),
     Bool(bool),
+    Vec3([f32; 3]),
+    Vec4([f32; 4]),
+    /// Column-major, as GLSL expects.
+    Mat4([f32; 16]),
+}
+
+impl Value {
+    /// Bytes written to the push constants, has to match the declared field.
+    fn byte_size(&self) -> usize {
+        match self {
+            Value::F32(_) | Value::U32(_) => 4,
+            Value::Bool(_) => mem::size_of::<bool>(),
+            Value::Vec3(_) => 12,
+            Value::Vec4(_) => 16,
+            Value::Mat4(_) => 64,
+        }
+    }
 }
 
+/// Which instance of a multi-buffered resource is bound, see `Vulkan::select_instance`.
+#[derive(Clone)]
+pub enum InstanceSelection {
+    /// Instance `frame_index % num_instances`, the default.
+    Frame,
+    /// Always the same instance.
+    Pinned(usize),
+    /// The instance most recently written using `MultiBuffer::write_current`, or selected by
+    /// frame until the first write.
+    LatestWritten(Rc<MultiBuffer>),
+}
+
+/// Every instance of an image holds one or more (for descriptor arrays) views and samplers.
 type AvailableImages = HashMap<
     String,
     Vec<(
-        Rc<ImageView>,
-        Rc<Sampler>,
-        Box<[vk::DescriptorImageInfo; 1]>,
+        Vec<(Rc<ImageView>, Rc<Sampler>)>,
+        Box<[vk::DescriptorImageInfo]>,
     )>,
 >;
 type AvailableBuffers = HashMap<String, Vec<(Rc<Buffer>, Box<[vk::DescriptorBufferInfo; 1]>)>>;
 
+/// The write descriptor set of the push descriptor set and the allocated sets by set index.
+type DescriptorSetBindings = (Vec<vk::WriteDescriptorSet>, Vec<(usize, vk::DescriptorSet)>);
+
 struct ShaderResources {
     // Pipelines.
     pipeline: Rc<Pipeline>,
     pipeline_layout: Rc<PipelineLayout>,
 
     // Descriptors.
-    _descriptor_layout: Rc<DescriptorLayout>,
+    allocated_descriptor_sets: Vec<Option<AllocatedDescriptorSets>>,
+    descriptor_layouts: Vec<Rc<DescriptorLayout>>,
     descriptors: Descriptors,
 
     // Compute shader.
@@ -61,24 +169,54 @@ struct ShaderResources {
 }
 
 impl ShaderResources {
-    pub unsafe fn new(device: &Rc<Device>, shader_path: &Path) -> VResult<Self> {
+    pub unsafe fn new(
+        device: &Rc<Device>,
+        shader_path: &Path,
+        present_name: &str,
+    ) -> VResult<Self> {
         // Compute shader.
         let shader_module_mtime = mtime(shader_path)?;
         let shader_module = ShaderModule::new(device, shader_path)?;
+        Self::with_module(device, shader_module, shader_module_mtime, present_name)
+    }
 
+    /// Resources of a built-in shader, which is never recompiled.
+    unsafe fn from_source(
+        device: &Rc<Device>,
+        name: &Path,
+        source: &str,
+        present_name: &str,
+    ) -> VResult<Self> {
+        let shader_module = ShaderModule::from_source(device, name, source)?;
+        Self::with_module(device, shader_module, FileTime::zero(), present_name)
+    }
+
+    unsafe fn with_module(
+        device: &Rc<Device>,
+        shader_module: Rc<ShaderModule>,
+        shader_module_mtime: FileTime,
+        present_name: &str,
+    ) -> VResult<Self> {
         // Descriptors.
-        let descriptors = Descriptors::new(&shader_module);
-        let descriptor_layout = DescriptorLayout::new(device, &descriptors)?;
+        let descriptors = Descriptors::new(&shader_module, present_name, device);
+        let descriptor_layouts = (0..descriptors.num_sets())
+            .map(|set| {
+                let push_descriptor = Some(set) == descriptors.push_set();
+                DescriptorLayout::new(device, &descriptors, set, push_descriptor)
+            })
+            .collect::<VResult<Vec<_>>>()?;
+        let allocated_descriptor_sets = descriptor_layouts.iter().map(|_| None).collect();
 
         // Pipelines.
-        let pipeline_layout = PipelineLayout::new(device, &shader_module, &descriptor_layout)?;
+        let pipeline_layout = PipelineLayout::new(device, &shader_module, &descriptor_layouts)?;
         let pipeline = Pipeline::new(device, &shader_module, &pipeline_layout)?;
 
         Ok(Self {
             shader_module_mtime,
             shader_module,
             descriptors,
-            _descriptor_layout: descriptor_layout,
+            descriptor_layouts,
+            allocated_descriptor_sets,
             pipeline_layout,
             pipeline,
         })
@@ -98,72 +236,220 @@ impl ShaderResources {
                 descriptor.instances.clear();
             }
         }
+        // Allocated descriptor sets may still be in use, they are replaced on reassociation.
+    }
+
+    fn pin_instances(&mut self, pinned_instances: &HashMap<String, usize>) {
+        // Allocated sets bake in the bound instances, so they are replaced if the pins change.
+        for set in self.descriptors.pin_instances(pinned_instances) {
+            self.allocated_descriptor_sets[set] = None;
+        }
     }
 
-    /// Collect, and, if missing, associate the available buffers with this shader.
-    fn get_write_descriptor_set(
+    /// Collect, and, if missing, associate the available buffers with this shader. Returns the
+    /// write descriptor set of the push descriptor set and the allocated descriptor sets to bind.
+    unsafe fn get_descriptor_sets(
         &mut self,
+        device: &Rc<Device>,
         available_images: &AvailableImages,
         available_buffers: &AvailableBuffers,
         present_name: &str,
         present_index: usize,
         frame_index: usize,
-    ) -> VResult<Vec<vk::WriteDescriptorSet>> {
-        self.descriptors.get_write_descriptor_set(
-            available_images,
-            available_buffers,
-            present_name,
-            present_index,
-            frame_index,
-        )
+    ) -> VResult<DescriptorSetBindings> {
+        let mut write_descriptor_set = Vec::new();
+        let mut descriptor_sets = Vec::new();
+
+        for set in 0..self.descriptors.num_sets() {
+            if Some(set) == self.descriptors.push_set() {
+                write_descriptor_set = self.descriptors.get_write_descriptor_set(
+                    set,
+                    available_images,
+                    available_buffers,
+                    present_name,
+                    present_index,
+                    frame_index,
+                )?;
+                continue;
+            }
+
+            if self.descriptors.needs_association(set)
+                || self.allocated_descriptor_sets[set].is_none()
+            {
+                self.allocated_descriptor_sets[set] =
+                    Some(self.descriptors.allocate_descriptor_sets(
+                        device,
+                        set,
+                        **self.descriptor_layouts[set],
+                        available_images,
+                        available_buffers,
+                        present_name,
+                    )?);
+            }
+            let allocated_descriptor_sets = self.allocated_descriptor_sets[set].as_ref().unwrap();
+            descriptor_sets.push((
+                set,
+                allocated_descriptor_sets.get(present_index, frame_index),
+            ));
+        }
+
+        Ok((write_descriptor_set, descriptor_sets))
     }
 }
 
+/// A window rendered to by the shared device, with its own swapchain and shader chain.
 // Define fields in reverse drop order.
-pub struct Vulkan {
-    // Other.
-    pub num_frames: usize,
+struct WindowTarget {
+    window_id: WindowId,
 
+    // Frame synchronization and profiling.
     image_acquired_semaphore: Rc<Semaphore>,
     compute_complete_semaphore: Rc<Semaphore>,
+    present_ready_semaphore: Rc<Semaphore>,
     reuse_command_buffer_fence: Rc<Fence>,
+    command_buffer: Rc<CommandBuffer>,
+    gpu_timer: GpuTimer,
+    pipeline_statistics: PipelineStatistics,
 
     // Shader modules, descriptor pools, sets and pipeline stuff.
     shader_resources: Vec<ShaderResources>,
+    /// Created when the overlay is first shown.
+    overlay: Option<ShaderResources>,
+    /// Window-sized image the overlay is drawn on when upscaling without FSR.
+    overlay_image: Option<Rc<MultiImage>>,
 
     // Swapchain.
     present_name: String,
+    window_extent: vk::Extent2D,
+    swapchain_outdated: bool,
+    present_command_buffers: Vec<Rc<CommandBuffer>>,
+    intermediate_present_image: Option<Rc<MultiImage>>,
+    upscale_filter: UpscaleFilter,
+    /// Upscaling passes between the intermediate image and the swapchain.
+    fsr: Option<Fsr>,
     swapchain_image_views: Vec<Rc<ImageView>>,
     swapchain_images: Vec<Rc<Image>>,
     swapchain: Option<Rc<Swapchain>>,
+
+    // Surface.
+    surface_info: SurfaceInfo,
+    /// Copy of the config with the window's present mode, render resolution and transparency.
+    config: VulkanConfig,
+    surface: Rc<Surface>,
+}
+
+impl WindowTarget {
+    fn swapchain(&self) -> &Rc<Swapchain> {
+        match &self.swapchain {
+            None => panic!("Did not expect missing swapchain here!"),
+            Some(ref swapchain) => swapchain,
+        }
+    }
+
+    fn blit(&self) -> bool {
+        self.surface_info.present_strategy == PresentStrategy::Blit
+    }
+
+    /// Layout and pipeline stage in which swapchain images are written.
+    fn swapchain_write_layout_and_stage(&self) -> (vk::ImageLayout, vk::PipelineStageFlags) {
+        match self.surface_info.present_strategy {
+            PresentStrategy::Blit => (
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                vk::PipelineStageFlags::TRANSFER,
+            ),
+            PresentStrategy::Auto | PresentStrategy::Direct => (
+                vk::ImageLayout::GENERAL,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+            ),
+        }
+    }
+
+    fn insert_mouse_push_constants(
+        &self,
+        input: &InputState,
+        push_constant_values: &mut HashMap<String, Value>,
+    ) {
+        // The cursor is reported in window pixels.
+        let render_resolution = self.surface_info.render_resolution;
+        let window_resolution = self.surface_info.surface_resolution;
+        #[allow(clippy::cast_precision_loss)]
+        let scale = (
+            render_resolution.width as f32 / window_resolution.width.max(1) as f32,
+            render_resolution.height as f32 / window_resolution.height.max(1) as f32,
+        );
+        // Windows the cursor is not in get zeros.
+        let (x, y, delta_x, delta_y, wheel, buttons) =
+            if input.cursor_window() == Some(self.window_id) {
+                let (x, y) = input.mouse_position();
+                let (delta_x, delta_y) = input.mouse_delta();
+                (
+                    x,
+                    y,
+                    delta_x,
+                    delta_y,
+                    input.wheel().1,
+                    input.mouse_buttons(),
+                )
+            } else {
+                (0f32, 0f32, 0f32, 0f32, 0f32, 0)
+            };
+        for (name, value) in [
+            ("mouse_x", x * scale.0),
+            ("mouse_y", y * scale.1),
+            ("mouse_delta_x", delta_x * scale.0),
+            ("mouse_delta_y", delta_y * scale.1),
+            ("mouse_wheel", wheel),
+        ] {
+            push_constant_values.insert(name.to_owned(), Value::F32(value));
+        }
+        push_constant_values.insert("mouse_buttons".to_owned(), Value::U32(buttons));
+    }
+}
+
+// Define fields in reverse drop order.
+pub struct Vulkan {
+    // Other.
+    pub num_frames: usize,
+    redraw_requested: Rc<RedrawRequest>,
+    input: Option<cell::Cell<InputState>>,
+    overlay: Overlay,
+    reuse_command_buffer_fence: Rc<Fence>,
+
+    // Windows, the main window first.
+    targets: Vec<WindowTarget>,
+    shader_errors: HashMap<String, String>,
     swapchain_loader: SwapchainLoader,
 
     // Resources.
     available_buffers: AvailableBuffers,
     available_images: AvailableImages,
+    bindless_tables: HashMap<String, Vec<Rc<MultiImage>>>,
+    instance_selections: HashMap<String, InstanceSelection>,
+    managed_images: Vec<ManagedImage>,
 
     // Staleness markers.
     stale_images: Vec<(String, Rc<Image>, vk::ImageLayout, vk::ImageLayout)>,
+    images_to_clear: Vec<Rc<Image>>,
 
     // Image data.
     sampler: Rc<Sampler>,
     image_subresource_range: vk::ImageSubresourceRange,
-    pub surface_info: SurfaceInfo,
-    vsync: bool,
+    config: VulkanConfig,
 
     // Device.
-    push_descriptor: PushDescriptor,
+    push_descriptor: Option<PushDescriptor>,
     command_buffer: Rc<CommandBuffer>,
-    _command_pool: Rc<CommandPool>,
+    command_pool: Rc<CommandPool>,
+    present_command_pool: Rc<CommandPool>,
     compute_queue: vk::Queue,
+    present_queue: vk::Queue,
     device: Rc<Device>,
     physical_device: Rc<PhysicalDevice>,
-    surface: Rc<Surface>,
     surface_loader: SurfaceLoader,
 
     // Core.
-    _instance: Rc<Instance>,
-    _entry: ash::Entry,
+    instance: Rc<Instance>,
+    entry: ash::Entry,
 }
 
 impl Vulkan {
@@ -171,27 +457,46 @@ impl Vulkan {
         window: &Window,
         compute_shader_paths: &[impl Deref<Target = Path>],
         vsync: bool,
+    ) -> VResult<Self> {
+        let present_mode = if vsync {
+            PresentMode::Fifo
+        } else {
+            PresentMode::Immediate
+        };
+        let config = VulkanConfig::default().present_mode(present_mode);
+        Self::with_config(window, compute_shader_paths, &config)
+    }
+
+    pub fn with_config(
+        window: &Window,
+        compute_shader_paths: &[impl Deref<Target = Path>],
+        config: &VulkanConfig,
     ) -> VResult<Self> {
         debug!("Initializing video system");
         unsafe {
             // Core.
             let entry = ash::Entry::linked();
-            let instance = Instance::new(window, &entry)?;
+            let instance = Instance::new(window, &entry, config)?;
 
             // Device.
             let surface_loader = SurfaceLoader::new(&entry, &instance);
             let surface = Surface::new(window, &entry, &instance, &surface_loader)?;
-            let physical_device = PhysicalDevice::new(&instance, &surface)?;
-            let device = Device::new(&instance, &physical_device)?;
+            let physical_device = PhysicalDevice::new(&instance, &surface, &config.device)?;
+            let device = Device::new(&instance, &physical_device, config)?;
             let compute_queue =
                 device.get_device_queue(physical_device.compute_queue_family_index, 0);
-            let command_pool = CommandPool::new(&physical_device, &device)?;
+            let present_queue =
+                device.get_device_queue(physical_device.present_queue_family_index, 0);
+            let command_pool =
+                CommandPool::new(&device, physical_device.compute_queue_family_index)?;
             let command_buffer = CommandBuffer::new(&device, &command_pool)?;
-            let push_descriptor = PushDescriptor::new(&instance, &device);
+            let present_command_pool =
+                CommandPool::new(&device, physical_device.present_queue_family_index)?;
+            let push_descriptor = device
+                .push_descriptor
+                .then(|| PushDescriptor::new(&instance, &device));
 
             // Image data.
-            let surface_info =
-                SurfaceInfo::new(&physical_device, &surface_loader, &surface, vsync)?;
             let image_subresource_range = vk::ImageSubresourceRange {
                 aspect_mask: vk::ImageAspectFlags::COLOR,
                 base_mip_level: 0,
@@ -204,91 +509,349 @@ impl Vulkan {
 
             // Staleness markers.
             let stale_images = Vec::new();
+            let images_to_clear = Vec::new();
 
             // Resources.
             let available_images = HashMap::new();
             let available_buffers = HashMap::new();
+            let bindless_tables = HashMap::new();
+            let instance_selections = HashMap::new();
+            let managed_images = Vec::new();
 
-            // Swapchain.
+            // Windows.
             let swapchain_loader = SwapchainLoader::new(&instance, &device);
-            let swapchain = None;
-            let swapchain_images = Vec::new();
-            let swapchain_image_views = Vec::new();
-            let present_name = "present".to_owned();
-
-            let shader_resources = compute_shader_paths
-                .iter()
-                .map(|path| ShaderResources::new(&device, path))
-                .collect::<VResult<_>>()?;
 
             let reuse_command_buffer_fence = Fence::new(&device)?;
-            let image_acquired_semaphore = Semaphore::new(&device)?;
-            let compute_complete_semaphore = Semaphore::new(&device)?;
 
             let mut vulkan = Self {
-                _entry: entry,
-                _instance: instance,
+                entry,
+                instance,
                 surface_loader,
-                surface,
                 physical_device,
                 device,
                 compute_queue,
-                _command_pool: command_pool,
+                present_queue,
+                command_pool,
+                present_command_pool,
                 command_buffer,
                 push_descriptor,
-                vsync,
-                surface_info,
+                config: config.clone(),
                 image_subresource_range,
                 sampler,
                 stale_images,
+                images_to_clear,
                 available_images,
                 available_buffers,
+                bindless_tables,
+                instance_selections,
+                managed_images,
                 swapchain_loader,
-                swapchain,
-                swapchain_images,
-                swapchain_image_views,
-                present_name,
-                shader_resources,
+                shader_errors: HashMap::new(),
+                targets: Vec::new(),
                 reuse_command_buffer_fence,
-                image_acquired_semaphore,
-                compute_complete_semaphore,
                 num_frames: 0,
+                redraw_requested: Rc::new(RedrawRequest::new()),
+                input: None,
+                overlay: Overlay::new(config.overlay, config.overlay_key),
             };
 
+            let target =
+                vulkan.new_window_target(window, surface, compute_shader_paths, "present")?;
+            vulkan.targets.push(target);
+            vulkan.name_shader_resources();
             vulkan.reinitialize_swapchain()?;
 
             Ok(vulkan)
         }
     }
 
+    unsafe fn new_window_target(
+        &self,
+        window: &Window,
+        surface: Rc<Surface>,
+        compute_shader_paths: &[impl Deref<Target = Path>],
+        present_name: &str,
+    ) -> VResult<WindowTarget> {
+        let config = VulkanConfig {
+            transparent: self.config.transparent || window.is_transparent(),
+            ..self.config.clone()
+        };
+        let (width, height) = window.size();
+        let window_extent = vk::Extent2D { width, height };
+        let surface_info = SurfaceInfo::new(
+            &self.instance,
+            &self.physical_device,
+            &self.surface_loader,
+            &surface,
+            &config,
+            window_extent,
+        )?;
+
+        let shader_resources = compute_shader_paths
+            .iter()
+            .map(|path| ShaderResources::new(&self.device, path, present_name))
+            .collect::<VResult<Vec<_>>>()?;
+        let gpu_timer = GpuTimer::new(
+            &self.device,
+            &self.physical_device,
+            config.gpu_timing,
+            config.gpu_timing_alpha,
+            shader_resources.len(),
+        )?;
+        let pipeline_statistics = PipelineStatistics::new(&self.device, shader_resources.len())?;
+
+        Ok(WindowTarget {
+            surface,
+            upscale_filter: config.upscale_filter,
+            config,
+            surface_info,
+            swapchain: None,
+            swapchain_images: Vec::new(),
+            swapchain_image_views: Vec::new(),
+            intermediate_present_image: None,
+            fsr: None,
+            present_command_buffers: Vec::new(),
+            swapchain_outdated: false,
+            window_extent,
+            present_name: present_name.to_owned(),
+            overlay: None,
+            overlay_image: None,
+            shader_resources,
+            pipeline_statistics,
+            gpu_timer,
+            command_buffer: CommandBuffer::new(&self.device, &self.command_pool)?,
+            reuse_command_buffer_fence: Fence::new(&self.device)?,
+            present_ready_semaphore: Semaphore::new(&self.device)?,
+            compute_complete_semaphore: Semaphore::new(&self.device)?,
+            image_acquired_semaphore: Semaphore::new(&self.device)?,
+            window_id: window.id(),
+        })
+    }
+
+    /// Render `compute_shader_paths` to another window, e.g. a debug view next to the main
+    /// output. All buffers and images are shared with the other windows. The shaders write to
+    /// the image `present_name`, which has to be unique. Route the window's events, e.g. `Resize`
+    /// to `resize_window`, in `App::handle_window_event`.
+    pub fn add_window(
+        &mut self,
+        window: &Window,
+        compute_shader_paths: &[impl Deref<Target = Path>],
+        present_name: &str,
+    ) -> VResult<()> {
+        if self.target(window.id()).is_some() {
+            return Err(Error::Local("Window was already added".to_owned()));
+        }
+        if self
+            .targets
+            .iter()
+            .any(|target| target.present_name == present_name)
+        {
+            let msg = format!("Present image {present_name:?} is used by another window");
+            return Err(Error::Local(msg));
+        }
+
+        unsafe {
+            let surface = Surface::new(window, &self.entry, &self.instance, &self.surface_loader)?;
+            if !surface.supports_present(
+                **self.physical_device,
+                self.physical_device.present_queue_family_index,
+            )? {
+                let msg = "The present queue can't present to the window".to_owned();
+                return Err(Error::Local(msg));
+            }
+            let target =
+                self.new_window_target(window, surface, compute_shader_paths, present_name)?;
+            self.targets.push(target);
+            self.name_shader_resources();
+            self.reinitialize_target_swapchain(self.targets.len() - 1)?;
+        }
+        self.redraw_requested.request();
+        Ok(())
+    }
+
+    /// Stop rendering to a window added with `add_window`, e.g. before it is closed. Its present
+    /// image is removed from bindless tables, which shifts the IDs of later entries.
+    pub fn remove_window(&mut self, window_id: WindowId) {
+        let Some(index) = self
+            .targets
+            .iter()
+            .position(|target| target.window_id == window_id)
+        else {
+            return;
+        };
+        if index == 0 {
+            warn!("The main window can't be removed");
+            return;
+        }
+
+        self.wait_idle();
+        let target = self.targets.remove(index);
+        let mut names = vec![target.present_name.clone()];
+        names.extend(fsr::image_names(&target.present_name));
+        names.push(overlay::image_name(&target.present_name));
+        let images = target
+            .intermediate_present_image
+            .iter()
+            .chain(target.fsr.iter().flat_map(Fsr::images))
+            .chain(&target.overlay_image)
+            .cloned()
+            .collect::<Vec<_>>();
+        self.unregister_images(&names, &images);
+        self.invalidate_shader_association_cache();
+    }
+
+    /// The windows rendered to, the main window first.
+    #[must_use]
+    pub fn window_ids(&self) -> Vec<WindowId> {
+        self.targets.iter().map(|target| target.window_id).collect()
+    }
+
+    fn target(&self, window_id: WindowId) -> Option<&WindowTarget> {
+        self.targets
+            .iter()
+            .find(|target| target.window_id == window_id)
+    }
+
+    fn target_mut(&mut self, window_id: WindowId) -> Option<&mut WindowTarget> {
+        self.targets
+            .iter_mut()
+            .find(|target| target.window_id == window_id)
+    }
+
+    fn main_target(&self) -> &WindowTarget {
+        &self.targets[0]
+    }
+
     fn invalidate_shader_association_cache(&mut self) {
-        for resources in &mut self.shader_resources {
-            resources.invalidate_association_cache();
+        for target in &mut self.targets {
+            for resources in target
+                .shader_resources
+                .iter_mut()
+                .chain(&mut target.overlay)
+            {
+                resources.invalidate_association_cache();
+            }
+            if let Some(fsr) = &mut target.fsr {
+                fsr.invalidate_association_cache();
+            }
         }
     }
 
-    fn swapchain(&self) -> &Rc<Swapchain> {
-        match &self.swapchain {
-            None => panic!("Did not expect missing swapchain here!"),
-            Some(ref swapchain) => swapchain,
+    /// List all physical devices with their properties and limits. Use
+    /// `physical_device::enumerate` to list them before choosing one.
+    pub fn enumerate_physical_devices(&self) -> VResult<Vec<PhysicalDeviceInfo>> {
+        let physical_devices =
+            unsafe { PhysicalDevice::enumerate(&self.instance, &self.main_target().surface)? };
+        Ok(physical_devices.into_iter().map(|(_, info)| info).collect())
+    }
+
+    /// Properties and limits of the physical device in use.
+    #[must_use]
+    pub fn physical_device_info(&self) -> &PhysicalDeviceInfo {
+        &self.physical_device.info
+    }
+
+    /// Surface format, resolutions and image count of the main window.
+    #[must_use]
+    pub fn surface_info(&self) -> &SurfaceInfo {
+        &self.main_target().surface_info
+    }
+
+    /// Label `object` with `name` in validation messages and debuggers such as ngfx.
+    pub fn set_object_name<H: vk::Handle>(&self, object: H, name: &str) {
+        unsafe {
+            self.instance
+                .set_object_name(self.device.handle(), object, name);
         }
     }
 
+    fn name_shader_resources(&self) {
+        for resources in self
+            .targets
+            .iter()
+            .flat_map(|target| &target.shader_resources)
+        {
+            let name = resources.shader_module.source_path.display().to_string();
+            self.set_object_name(**resources.shader_module, &name);
+            self.set_object_name(**resources.pipeline, &name);
+        }
+    }
+
+    fn shaders_modified(&self) -> bool {
+        self.targets
+            .iter()
+            .flat_map(|target| &target.shader_resources)
+            .any(|resources| {
+                matches!(
+                    mtime(&resources.shader_module.source_path),
+                    Ok(mtime) if mtime > resources.shader_module_mtime
+                )
+            })
+    }
+
+    /// Feed the mouse state into the built-in push constants `mouse_x` and `mouse_y` (in render
+    /// pixels), `mouse_delta_x`, `mouse_delta_y`, `mouse_buttons` (left 1, right 2, middle 4)
+    /// and `mouse_wheel` (accumulated lines). See `EventLoop::input`.
+    pub fn set_input(&mut self, input: cell::Cell<InputState>) {
+        self.input = Some(input);
+    }
+
+    /// The error of the last failed recompilation of each shader, keyed by path. Shaders keep
+    /// running their last working version until they compile again.
+    #[must_use]
+    pub fn shader_errors(&self) -> &HashMap<String, String> {
+        &self.shader_errors
+    }
+
+    /// Request a frame for on-demand rendering, e.g. after changing push constants.
+    pub fn request_redraw(&self) {
+        self.redraw_requested.request();
+    }
+
+    /// Wake an on-demand `EventLoop` whenever a redraw is requested, see `needs_redraw`.
+    pub fn set_event_loop_proxy(&self, proxy: EventLoopProxy<()>) {
+        *self.redraw_requested.proxy.borrow_mut() = Some(proxy);
+    }
+
+    /// Whether the next `tick` would change the output: a redraw was requested, a buffer was
+    /// written through `MultiBuffer::write_current` or `mark_written`, a shader was modified or
+    /// a swapchain is out of date. Use it to implement `App::needs_redraw`.
+    #[must_use]
+    pub fn needs_redraw(&self) -> bool {
+        self.redraw_requested.is_requested()
+            || self.targets.iter().any(|target| target.swapchain_outdated)
+            || self.shaders_modified()
+    }
+
     unsafe fn recompile_shader_if_modified(&mut self) -> VResult<()> {
-        for index in 0..self.shader_resources.len() {
-            let path = &self.shader_resources[index].shader_module.source_path;
-            let previous_mtime = self.shader_resources[index].shader_module_mtime;
-            if mtime(path)? > previous_mtime {
-                info!("Recompiling {path:?} ...");
-                self.wait_idle();
-
-                let new_resources = ShaderResources::new(&self.device, path);
-
-                match new_resources {
-                    Ok(new_resources) => self.shader_resources[index] = new_resources,
-                    Err(err) => {
-                        error!("{err}");
-                        self.shader_resources[index].shader_module_mtime = mtime(path)?;
+        for target in 0..self.targets.len() {
+            for index in 0..self.targets[target].shader_resources.len() {
+                let window = &self.targets[target];
+                let path = window.shader_resources[index]
+                    .shader_module
+                    .source_path
+                    .clone();
+                let previous_mtime = window.shader_resources[index].shader_module_mtime;
+                if mtime(&path)? > previous_mtime {
+                    info!("Recompiling {path:?} ...");
+                    self.wait_idle();
+
+                    let new_resources =
+                        ShaderResources::new(&self.device, &path, &window.present_name);
+
+                    let name = path.display().to_string();
+                    match new_resources {
+                        Ok(new_resources) => {
+                            self.targets[target].shader_resources[index] = new_resources;
+                            self.shader_errors.remove(&name);
+                            self.name_shader_resources();
+                        }
+                        Err(err) => {
+                            error!("{err}");
+                            self.shader_errors.insert(name, err.to_string());
+                            self.targets[target].shader_resources[index].shader_module_mtime =
+                                mtime(&path)?;
+                        }
                     }
                 }
             }
@@ -299,16 +862,31 @@ impl Vulkan {
     fn register_image(&mut self, name: &str, views_and_samplers: &[(Rc<ImageView>, Rc<Sampler>)]) {
         let instances = views_and_samplers
             .iter()
-            .map(|(image_view, sampler)| {
-                (
-                    image_view.clone(),
-                    sampler.clone(),
-                    Box::new([vk::DescriptorImageInfo::builder()
-                        .image_view(***image_view)
-                        .sampler(***sampler)
-                        .image_layout(vk::ImageLayout::GENERAL)
-                        .build()]),
-                )
+            .map(|view_and_sampler| vec![view_and_sampler.clone()])
+            .collect::<Vec<_>>();
+        self.register_image_array(name, &instances);
+    }
+
+    /// Register an image array. Every instance holds the elements of the array.
+    fn register_image_array(
+        &mut self,
+        name: &str,
+        instances: &[Vec<(Rc<ImageView>, Rc<Sampler>)>],
+    ) {
+        let instances = instances
+            .iter()
+            .map(|elements| {
+                let image_infos = elements
+                    .iter()
+                    .map(|(image_view, sampler)| {
+                        vk::DescriptorImageInfo::builder()
+                            .image_view(***image_view)
+                            .sampler(***sampler)
+                            .image_layout(vk::ImageLayout::GENERAL)
+                            .build()
+                    })
+                    .collect();
+                (elements.clone(), image_infos)
             })
             .collect();
         self.available_images.insert(name.to_owned(), instances);
@@ -333,14 +911,22 @@ impl Vulkan {
         self.invalidate_shader_association_cache();
     }
 
+    /// Recreate the swapchains of all windows.
     pub unsafe fn reinitialize_swapchain(&mut self) -> VResult<()> {
+        for target in 0..self.targets.len() {
+            self.reinitialize_target_swapchain(target)?;
+        }
+        Ok(())
+    }
+
+    unsafe fn reinitialize_target_swapchain(&mut self, target: usize) -> VResult<()> {
         debug!("Reinitializing swapchain");
         self.wait_idle();
 
-        // After a resize (TODO Fix function name) all images need to be reinitialized anyway. The
-        // case where not clearing this vector leads to issues is rather special, it occurs once on
-        // app start and when there are two consecutive resize events.
-        self.stale_images.clear();
+        // The swapchain (or intermediate) images are replaced, so their pending transitions are
+        // obsolete. Other images, e.g. created since the last frame, still need theirs.
+        let present_name = self.targets[target].present_name.clone();
+        self.stale_images.retain(|(name, ..)| *name != present_name);
 
         // TODO
         // The following code first creates the new resources, replaces them in `self` and only
@@ -348,102 +934,380 @@ impl Vulkan {
         // OOM errors. Alternative solutions: wrap all fields in `Option` or separate between free
         // and `drop`.
 
-        self.surface_info = SurfaceInfo::new(
+        let window = &mut self.targets[target];
+        // The overlay is drawn on the output image, which depends on the new surface.
+        window.overlay = None;
+        window.overlay_image = None;
+        window.pipeline_statistics.clear_reports();
+        window.surface_info = SurfaceInfo::new(
+            &self.instance,
             &self.physical_device,
             &self.surface_loader,
-            &self.surface,
-            self.vsync,
+            &window.surface,
+            &window.config,
+            window.window_extent,
         )?;
-        self.swapchain = Some(Swapchain::new(
-            &self.surface,
-            &self.surface_info,
+        window.swapchain = Some(Swapchain::new(
+            &window.surface,
+            &window.surface_info,
             &self.swapchain_loader,
-            self.swapchain.as_ref().map(|swapchain| ***swapchain),
+            window.swapchain.as_ref().map(|swapchain| ***swapchain),
         )?);
-        self.swapchain_images =
-            Image::many_from_swapchain(&self.swapchain_loader, self.swapchain())?;
+        window.swapchain_images =
+            Image::many_from_swapchain(&self.swapchain_loader, window.swapchain())?;
+        let swapchain_images = window.swapchain_images.clone();
+        let blit = window.blit();
+        let swapchain_name = if blit {
+            format!("{present_name} swapchain")
+        } else {
+            present_name.clone()
+        };
+        for (index, image) in swapchain_images.iter().enumerate() {
+            self.set_object_name(***image, &format!("{swapchain_name}[{index}]"));
+        }
 
-        for image in &self.swapchain_images {
+        for image in &swapchain_images {
             self.stale_images.push((
-                self.present_name.clone(),
+                present_name.clone(),
                 image.clone(),
                 vk::ImageLayout::UNDEFINED,
                 vk::ImageLayout::PRESENT_SRC_KHR,
             ));
         }
-        self.swapchain_image_views = ImageView::many(
-            &self.device,
-            self.swapchain_images.iter(),
-            self.surface_info.surface_format.format,
-            &self.image_subresource_range,
-        )?;
-        let views_and_samplers = self
-            .swapchain_image_views
+
+        let window = &mut self.targets[target];
+        if blit {
+            // Shaders render to an intermediate image, one instance per swapchain image.
+            window.swapchain_image_views = Vec::new();
+            window.intermediate_present_image = None;
+            let format = window.config.intermediate_format;
+            let format_features = self
+                .instance
+                .get_physical_device_format_properties(**self.physical_device, format)
+                .optimal_tiling_features;
+            if !format_features
+                .contains(vk::FormatFeatureFlags::STORAGE_IMAGE | vk::FormatFeatureFlags::BLIT_SRC)
+            {
+                let msg = format!("Intermediate format {format:?} can't be stored and blitted");
+                return Err(Error::Local(msg));
+            }
+            window.upscale_filter = window.config.upscale_filter;
+            let fsr_features = self
+                .instance
+                .get_physical_device_format_properties(**self.physical_device, fsr::FORMAT)
+                .optimal_tiling_features;
+            if window.upscale_filter == UpscaleFilter::Fsr
+                && !fsr_features.contains(
+                    vk::FormatFeatureFlags::STORAGE_IMAGE
+                        | vk::FormatFeatureFlags::SAMPLED_IMAGE
+                        | vk::FormatFeatureFlags::BLIT_SRC,
+                )
+            {
+                warn!("FSR needs {:?} storage images, using linear", fsr::FORMAT);
+                window.upscale_filter = UpscaleFilter::Linear;
+            }
+            if window.upscale_filter == UpscaleFilter::Linear
+                && !format_features.contains(vk::FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR)
+            {
+                warn!("Intermediate format {format:?} can't be filtered linearly, using nearest");
+                window.upscale_filter = UpscaleFilter::Nearest;
+            }
+            let render_resolution = window.surface_info.render_resolution;
+            let intermediate_present_image = self.create_multi_image(
+                &present_name,
+                format,
+                render_resolution,
+                Some(swapchain_images.len()),
+            )?;
+            self.targets[target].intermediate_present_image = Some(intermediate_present_image);
+            if self.targets[target].upscale_filter == UpscaleFilter::Fsr {
+                self.reinitialize_fsr(target)?;
+            } else {
+                self.targets[target].fsr = None;
+            }
+        } else {
+            window.intermediate_present_image = None;
+            window.fsr = None;
+            window.swapchain_image_views = ImageView::many(
+                &self.device,
+                swapchain_images.iter(),
+                window.surface_info.surface_format.format,
+                &self.image_subresource_range,
+            )?;
+            let views_and_samplers = window
+                .swapchain_image_views
+                .iter()
+                .map(|image_view| (image_view.clone(), self.sampler.clone()))
+                .collect::<Vec<_>>();
+            self.register_image(&present_name, &views_and_samplers);
+        }
+
+        self.record_present_command_buffers(target)?;
+        // Screen-relative images follow the render resolution of the main window.
+        if target == 0 {
+            self.recreate_screen_sized_images()?;
+        }
+
+        Ok(())
+    }
+
+    /// Smoothed GPU milliseconds per shader pass of all windows, keyed by shader path. Trails
+    /// one frame behind.
+    #[must_use]
+    pub fn gpu_timings(&self) -> Vec<(&str, f32)> {
+        self.targets
             .iter()
-            .map(|image_view| (image_view.clone(), self.sampler.clone()))
-            .collect::<Vec<_>>();
+            .flat_map(|target| target.gpu_timer.timings())
+            .collect()
+    }
+
+    /// GPU timer of the main window.
+    #[must_use]
+    pub fn gpu_timer(&self) -> &GpuTimer {
+        &self.main_target().gpu_timer
+    }
+
+    /// Compute shader invocations per shader pass of the main window in the last finished frame.
+    /// A pass with fewer invocations than render pixels is logged as a warning.
+    #[must_use]
+    pub fn pipeline_statistics(&self) -> &PipelineStatistics {
+        &self.main_target().pipeline_statistics
+    }
+
+    /// The present mode in use, which may differ from the requested one if it is unsupported.
+    #[must_use]
+    pub fn present_mode(&self) -> PresentMode {
+        self.main_target().surface_info.present_mode
+    }
+
+    #[must_use]
+    pub fn supported_present_modes(&self) -> &[PresentMode] {
+        &self.main_target().surface_info.supported_present_modes
+    }
+
+    /// Switch the present mode of all windows. The swapchains are recreated on the next tick.
+    pub fn set_present_mode(&mut self, present_mode: PresentMode) {
+        info!("Switching to present mode {present_mode:?}");
+        self.config.present_mode = present_mode;
+        for target in &mut self.targets {
+            target.config.present_mode = present_mode;
+            target.swapchain_outdated = true;
+        }
+    }
+
+    /// Choose which instance of the image or buffer `name` is bound. Changing the selection of
+    /// a binding outside of the push descriptor set rewrites its descriptor sets.
+    pub fn select_instance(&mut self, name: &str, selection: InstanceSelection) {
+        match selection {
+            InstanceSelection::Frame => self.instance_selections.remove(name),
+            selection => self.instance_selections.insert(name.to_owned(), selection),
+        };
+    }
+
+    fn pinned_instances(&self) -> HashMap<String, usize> {
+        self.instance_selections
+            .iter()
+            .filter_map(|(name, selection)| {
+                let index = match selection {
+                    InstanceSelection::Frame => None,
+                    InstanceSelection::Pinned(index) => Some(*index),
+                    InstanceSelection::LatestWritten(buffer) => buffer.latest_written(),
+                }?;
+                Some((name.clone(), index))
+            })
+            .collect()
+    }
+
+    /// The resolution shaders of the main window are dispatched at.
+    #[must_use]
+    pub fn render_resolution(&self) -> vk::Extent2D {
+        self.main_target().surface_info.render_resolution
+    }
+
+    /// Change the render resolution of the main window. The swapchain is recreated on the next
+    /// tick.
+    pub fn set_render_resolution(&mut self, render_resolution: SizePolicy) {
+        info!("Switching to render resolution {render_resolution:?}");
+        self.config.render_resolution = render_resolution;
+        let target = &mut self.targets[0];
+        target.config.render_resolution = render_resolution;
+        target.swapchain_outdated = true;
+    }
+
+    /// Notify about a new size of the main window, e.g. from `event_loop::Event::Resize`. The
+    /// swapchain is recreated on the next tick.
+    pub fn resize(&mut self, width: u32, height: u32) {
+        let window_id = self.main_target().window_id;
+        self.resize_window(window_id, width, height);
+    }
+
+    /// Notify about a new size of the window `window_id`, see `resize`.
+    pub fn resize_window(&mut self, window_id: WindowId, width: u32, height: u32) {
+        if let Some(target) = self.target_mut(window_id) {
+            target.window_extent = vk::Extent2D { width, height };
+            target.swapchain_outdated = true;
+        }
+    }
+
+    unsafe fn current_surface_resolution(&self, target: usize) -> VResult<vk::Extent2D> {
+        let window = &self.targets[target];
+        let surface_capabilities = self
+            .surface_loader
+            .get_physical_device_surface_capabilities(**self.physical_device, **window.surface)?;
+        Ok(SurfaceInfo::surface_resolution(
+            &surface_capabilities,
+            window.window_extent,
+        ))
+    }
 
-        let present_name = self.present_name.clone();
-        self.register_image(&present_name, &views_and_samplers);
+    fn separate_present_queue(&self) -> bool {
+        self.physical_device.compute_queue_family_index
+            != self.physical_device.present_queue_family_index
+    }
+
+    /// Record, for every swapchain image, a command buffer acquiring its ownership on the present
+    /// queue. These are only used if the compute queue cannot present.
+    unsafe fn record_present_command_buffers(&mut self, target: usize) -> VResult<()> {
+        self.targets[target].present_command_buffers.clear();
+        if !self.separate_present_queue() {
+            return Ok(());
+        }
+
+        let window = &self.targets[target];
+        let mut present_command_buffers = Vec::new();
+        for image in &window.swapchain_images {
+            let command_buffer = CommandBuffer::new(&self.device, &self.present_command_pool)?;
+            let command_buffer_begin_info = vk::CommandBufferBeginInfo::builder()
+                .flags(vk::CommandBufferUsageFlags::SIMULTANEOUS_USE);
+            self.device
+                .begin_command_buffer(**command_buffer, &command_buffer_begin_info)?;
+            self.queue_family_ownership_barrier(
+                window,
+                **command_buffer,
+                ***image,
+                vk::PipelineStageFlags::TOP_OF_PIPE,
+                vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+            );
+            self.device.end_command_buffer(**command_buffer)?;
+            present_command_buffers.push(command_buffer);
+        }
+        self.targets[target].present_command_buffers = present_command_buffers;
 
         Ok(())
     }
 
-    unsafe fn begin_command_buffer(&self) -> VResult<()> {
+    /// Transfer ownership of a swapchain image from the compute to the present queue family,
+    /// transitioning it to `PRESENT_SRC_KHR`. The same barrier has to be recorded on both queues,
+    /// releasing on the compute and acquiring on the present queue.
+    unsafe fn queue_family_ownership_barrier(
+        &self,
+        window: &WindowTarget,
+        command_buffer: vk::CommandBuffer,
+        image: vk::Image,
+        src_stage: vk::PipelineStageFlags,
+        dst_stage: vk::PipelineStageFlags,
+    ) {
+        let memory_barrier = vk::ImageMemoryBarrier::builder()
+            .image(image)
+            .subresource_range(self.image_subresource_range)
+            .old_layout(window.swapchain_write_layout_and_stage().0)
+            .new_layout(vk::ImageLayout::PRESENT_SRC_KHR)
+            .src_queue_family_index(self.physical_device.compute_queue_family_index)
+            .dst_queue_family_index(self.physical_device.present_queue_family_index)
+            .build();
+
+        self.device.cmd_pipeline_barrier(
+            command_buffer,
+            src_stage,
+            dst_stage,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[],
+            &[memory_barrier],
+        );
+    }
+
+    unsafe fn begin_command_buffer(&self, command_buffer: vk::CommandBuffer) -> VResult<()> {
         let command_buffer_begin_info = vk::CommandBufferBeginInfo::builder()
             .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
         Ok(self
             .device
-            .begin_command_buffer(**self.command_buffer, &command_buffer_begin_info)?)
+            .begin_command_buffer(command_buffer, &command_buffer_begin_info)?)
     }
 
-    unsafe fn end_command_buffer(&self) -> VResult<()> {
-        Ok(self.device.end_command_buffer(**self.command_buffer)?)
+    unsafe fn end_command_buffer(&self, command_buffer: vk::CommandBuffer) -> VResult<()> {
+        Ok(self.device.end_command_buffer(command_buffer)?)
     }
 
     unsafe fn queue_submit(
         &self,
+        command_buffer: &CommandBuffer,
+        fence: &Fence,
         wait_semaphores: &[vk::Semaphore],
         wait_semaphore_stages: &[vk::PipelineStageFlags],
         signal_semaphores: &[vk::Semaphore],
     ) -> VResult<()> {
         let submit_info = vk::SubmitInfo::builder()
-            .command_buffers(&[**self.command_buffer])
+            .command_buffers(&[**command_buffer])
             .wait_semaphores(wait_semaphores)
             .wait_dst_stage_mask(wait_semaphore_stages)
             .signal_semaphores(signal_semaphores)
             .build();
 
-        Ok(self.device.queue_submit(
-            self.compute_queue,
-            &[submit_info],
-            **self.reuse_command_buffer_fence,
-        )?)
+        Ok(self
+            .device
+            .queue_submit(self.compute_queue, &[submit_info], **fence)?)
     }
 
     unsafe fn queue_submit_task(&self) -> VResult<()> {
-        self.queue_submit(&[], &[], &[])
+        self.queue_submit(
+            &self.command_buffer,
+            &self.reuse_command_buffer_fence,
+            &[],
+            &[],
+            &[],
+        )
     }
 
-    unsafe fn queue_submit_compute(&self) -> VResult<()> {
+    unsafe fn queue_submit_compute(&self, target: usize) -> VResult<()> {
+        let window = &self.targets[target];
         self.queue_submit(
-            &[**self.image_acquired_semaphore],
-            &[vk::PipelineStageFlags::COMPUTE_SHADER],
-            &[**self.compute_complete_semaphore],
+            &window.command_buffer,
+            &window.reuse_command_buffer_fence,
+            &[**window.image_acquired_semaphore],
+            &[window.swapchain_write_layout_and_stage().1],
+            &[**window.compute_complete_semaphore],
         )
     }
 
-    unsafe fn bind_pipeline(&self, pipeline: &Pipeline) {
-        self.device.cmd_bind_pipeline(
-            **self.command_buffer,
-            vk::PipelineBindPoint::COMPUTE,
-            **pipeline,
-        );
+    /// Acquire the swapchain image on the present queue once compute has finished.
+    unsafe fn queue_submit_present(&self, target: usize, present_index: usize) -> VResult<()> {
+        let window = &self.targets[target];
+        let command_buffers = [**window.present_command_buffers[present_index]];
+        let wait_semaphores = [**window.compute_complete_semaphore];
+        let wait_semaphore_stages = [vk::PipelineStageFlags::ALL_COMMANDS];
+        let signal_semaphores = [**window.present_ready_semaphore];
+        let submit_info = vk::SubmitInfo::builder()
+            .command_buffers(&command_buffers)
+            .wait_semaphores(&wait_semaphores)
+            .wait_dst_stage_mask(&wait_semaphore_stages)
+            .signal_semaphores(&signal_semaphores)
+            .build();
+
+        Ok(self
+            .device
+            .queue_submit(self.present_queue, &[submit_info], vk::Fence::null())?)
+    }
+
+    unsafe fn bind_pipeline(&self, command_buffer: vk::CommandBuffer, pipeline: &Pipeline) {
+        self.device
+            .cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::COMPUTE, **pipeline);
     }
 
     // Requires bound pipeline and a started command buffer.
     unsafe fn image_memory_barrier_layout_transition(
         &self,
+        command_buffer: vk::CommandBuffer,
         image: vk::Image,
         old_layout: vk::ImageLayout,
         new_layout: vk::ImageLayout,
@@ -457,7 +1321,7 @@ impl Vulkan {
         let memory_barriers = [memory_barrier];
 
         self.device.cmd_pipeline_barrier(
-            **self.command_buffer,
+            command_buffer,
             vk::PipelineStageFlags::COMPUTE_SHADER,
             vk::PipelineStageFlags::COMPUTE_SHADER,
             vk::DependencyFlags::BY_REGION,
@@ -467,32 +1331,228 @@ impl Vulkan {
         );
     }
 
+    /// Copy the intermediate present image, or the output of the FSR passes or the overlay, to
+    /// the swapchain image, upscaling it from the render resolution. Transitions the swapchain
+    /// image to `PRESENT_SRC_KHR` unless it has to be released to a separate present queue.
+    unsafe fn blit_to_swapchain(
+        &self,
+        target: usize,
+        present_index: usize,
+        swapchain_image: vk::Image,
+        overlay: bool,
+    ) {
+        let window = &self.targets[target];
+        let command_buffer = **window.command_buffer;
+        let (intermediate_image, src_resolution) =
+            if let (true, Some(overlay_image)) = (overlay, &window.overlay_image) {
+                (&overlay_image[0], window.surface_info.surface_resolution)
+            } else if let Some(fsr) = &window.fsr {
+                (
+                    &fsr.upscaled_image()[0],
+                    window.surface_info.surface_resolution,
+                )
+            } else {
+                (
+                    &window.intermediate_present_image.as_ref().unwrap()[present_index],
+                    window.surface_info.render_resolution,
+                )
+            };
+
+        // Make shader writes visible to the blit, the swapchain contents are discarded.
+        let memory_barriers = [
+            vk::ImageMemoryBarrier::builder()
+                .image(**intermediate_image.image)
+                .subresource_range(self.image_subresource_range)
+                .old_layout(vk::ImageLayout::GENERAL)
+                .new_layout(vk::ImageLayout::GENERAL)
+                .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+                .dst_access_mask(vk::AccessFlags::TRANSFER_READ)
+                .build(),
+            vk::ImageMemoryBarrier::builder()
+                .image(swapchain_image)
+                .subresource_range(self.image_subresource_range)
+                .old_layout(vk::ImageLayout::UNDEFINED)
+                .new_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+                .dst_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+                .build(),
+        ];
+        self.device.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::COMPUTE_SHADER | vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[],
+            &memory_barriers,
+        );
+
+        let subresource = vk::ImageSubresourceLayers {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            mip_level: 0,
+            base_array_layer: 0,
+            layer_count: 1,
+        };
+        let offsets = |extent: vk::Extent2D| {
+            [
+                vk::Offset3D::default(),
+                vk::Offset3D {
+                    x: i32::try_from(extent.width).unwrap(),
+                    y: i32::try_from(extent.height).unwrap(),
+                    z: 1,
+                },
+            ]
+        };
+        let region = vk::ImageBlit {
+            src_subresource: subresource,
+            src_offsets: offsets(src_resolution),
+            dst_subresource: subresource,
+            dst_offsets: offsets(window.surface_info.surface_resolution),
+        };
+        self.device.cmd_blit_image(
+            command_buffer,
+            **intermediate_image.image,
+            vk::ImageLayout::GENERAL,
+            swapchain_image,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            &[region],
+            window.upscale_filter.vk_filter(),
+        );
+
+        if !self.separate_present_queue() {
+            let memory_barrier = vk::ImageMemoryBarrier::builder()
+                .image(swapchain_image)
+                .subresource_range(self.image_subresource_range)
+                .old_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+                .new_layout(vk::ImageLayout::PRESENT_SRC_KHR)
+                .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+                .build();
+            self.device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[memory_barrier],
+            );
+        }
+    }
+
+    /// Make the shader writes of previous dispatches visible to the next.
+    unsafe fn shader_write_barrier(&self, command_buffer: vk::CommandBuffer) {
+        let memory_barrier = vk::MemoryBarrier::builder()
+            .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+            .dst_access_mask(vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE)
+            .build();
+        self.device.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::DependencyFlags::empty(),
+            &[memory_barrier],
+            &[],
+            &[],
+        );
+    }
+
+    /// Clear an image to zero, discarding its contents. Leaves it in "GENERAL" layout.
+    unsafe fn clear_image(&self, command_buffer: vk::CommandBuffer, image: vk::Image) {
+        let memory_barrier = vk::ImageMemoryBarrier::builder()
+            .image(image)
+            .subresource_range(self.image_subresource_range)
+            .old_layout(vk::ImageLayout::UNDEFINED)
+            .new_layout(vk::ImageLayout::GENERAL)
+            .dst_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+            .build();
+        self.device.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[],
+            &[memory_barrier],
+        );
+        self.device.cmd_clear_color_image(
+            command_buffer,
+            image,
+            vk::ImageLayout::GENERAL,
+            &vk::ClearColorValue::default(),
+            &[self.image_subresource_range],
+        );
+    }
+
     unsafe fn transition_stale_images(&mut self) -> VResult<()> {
         self.reuse_command_buffer_fence.wait()?;
         self.reuse_command_buffer_fence.reset()?;
-        self.begin_command_buffer()?;
+        let command_buffer = **self.command_buffer;
+        self.begin_command_buffer(command_buffer)?;
         let stale_images = mem::take(&mut self.stale_images);
         for (_, image, old_layout, new_layout) in stale_images {
-            self.image_memory_barrier_layout_transition(**image, old_layout, new_layout);
+            self.image_memory_barrier_layout_transition(
+                command_buffer,
+                **image,
+                old_layout,
+                new_layout,
+            );
+        }
+        let images_to_clear = mem::take(&mut self.images_to_clear);
+        for image in &images_to_clear {
+            self.clear_image(command_buffer, ***image);
+        }
+        if !images_to_clear.is_empty() {
+            // Finish the clears before the shaders of the next frame access the images, the
+            // barrier applies to later submissions to the compute queue as well.
+            let memory_barrier = vk::MemoryBarrier::builder()
+                .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+                .dst_access_mask(vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE)
+                .build();
+            self.device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::DependencyFlags::empty(),
+                &[memory_barrier],
+                &[],
+                &[],
+            );
         }
-        self.end_command_buffer()?;
+        self.end_command_buffer(command_buffer)?;
         self.queue_submit_task()
     }
 
-    unsafe fn acquire_next_image(&self) -> VResult<(usize, vk::Image)> {
-        let (present_index, _) = self.swapchain_loader.acquire_next_image(
-            ***self.swapchain.as_ref().unwrap(),
+    /// Returns `None` if the swapchain is out of date.
+    unsafe fn acquire_next_image(&mut self, target: usize) -> VResult<Option<(usize, vk::Image)>> {
+        let window = &mut self.targets[target];
+        let acquire_result = self.swapchain_loader.acquire_next_image(
+            ***window.swapchain(),
             std::u64::MAX,
-            **self.image_acquired_semaphore,
+            **window.image_acquired_semaphore,
             vk::Fence::null(),
-        )?;
+        );
+        let present_index = match acquire_result {
+            Ok((present_index, suboptimal)) => {
+                // The semaphore is signaled, so render this frame and recreate afterwards.
+                window.swapchain_outdated |= suboptimal;
+                present_index
+            }
+            Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => {
+                window.swapchain_outdated = true;
+                return Ok(None);
+            }
+            Err(err) => return Err(err.into()),
+        };
         let present_index = usize::try_from(present_index).unwrap();
 
-        Ok((present_index, **self.swapchain_images[present_index]))
+        Ok(Some((
+            present_index,
+            **window.swapchain_images[present_index],
+        )))
     }
 
     unsafe fn push_constants(
         &self,
+        command_buffer: vk::CommandBuffer,
         pipeline_layout: &PipelineLayout,
         shader_module: &ShaderModule,
         push_constant_values: &HashMap<String, Value>,
@@ -514,8 +1574,21 @@ impl Vulkan {
                     warn!("Don't know how to handle {field:?}");
                 }
 
+                let value = push_constant_values.get(&field.name);
+                // Writing a larger value than declared would overflow `constants`.
+                if let Some(value) = value {
+                    if field.byte_size() != Some(value.byte_size()) {
+                        error!(
+                            "Push constant field {} is declared as {:?}, which doesn't match its \
+                             value",
+                            field.name, field.type_specifier
+                        );
+                        continue;
+                    }
+                }
+
                 #[allow(clippy::cast_ptr_alignment)]
-                match push_constant_values.get(&field.name) {
+                match value {
                     None => error!("{} is not a registered push constant field", field.name),
                     Some(Value::F32(value)) => {
                         *constants_ptr.add(offset).cast::<f32>() = *value;
@@ -525,13 +1598,22 @@ impl Vulkan {
                     }
                     Some(Value::Bool(value)) => {
                         *constants_ptr.add(offset).cast::<bool>() = *value;
+                    }
+                    Some(Value::Vec3(value)) => {
+                        *constants_ptr.add(offset).cast::<[f32; 3]>() = *value;
+                    }
+                    Some(Value::Vec4(value)) => {
+                        *constants_ptr.add(offset).cast::<[f32; 4]>() = *value;
+                    }
+                    Some(Value::Mat4(value)) => {
+                        *constants_ptr.add(offset).cast::<[f32; 16]>() = *value;
                     } // _ => warn!("Don't know how to handle {field:?}"),
                 }
             }
 
             // Update on GPU.
             self.device.cmd_push_constants(
-                **self.command_buffer,
+                command_buffer,
                 **pipeline_layout,
                 vk::ShaderStageFlags::COMPUTE,
                 0,
@@ -542,68 +1624,128 @@ impl Vulkan {
 
     unsafe fn push_descriptors(
         &self,
+        command_buffer: vk::CommandBuffer,
         pipeline_layout: &PipelineLayout,
+        set: Option<usize>,
         write_descriptor_set: &[vk::WriteDescriptorSet],
     ) {
-        self.push_descriptor.cmd_push_descriptor_set(
-            **self.command_buffer,
+        let (Some(push_descriptor), Some(set)) = (&self.push_descriptor, set) else {
+            return;
+        };
+
+        // Pushing zero descriptors is invalid.
+        if write_descriptor_set.is_empty() {
+            return;
+        }
+
+        push_descriptor.cmd_push_descriptor_set(
+            command_buffer,
             vk::PipelineBindPoint::COMPUTE,
             **pipeline_layout,
-            0,
+            u32::try_from(set).unwrap(),
             write_descriptor_set,
         );
     }
 
-    unsafe fn dispatch(&self, shader_module: &ShaderModule) {
+    unsafe fn bind_descriptor_sets(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        pipeline_layout: &PipelineLayout,
+        descriptor_sets: &[(usize, vk::DescriptorSet)],
+    ) {
+        for (set, descriptor_set) in descriptor_sets {
+            self.device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                **pipeline_layout,
+                u32::try_from(*set).unwrap(),
+                &[*descriptor_set],
+                &[],
+            );
+        }
+    }
+
+    unsafe fn dispatch(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        shader_module: &ShaderModule,
+        render_resolution: vk::Extent2D,
+    ) {
         let local_size = shader_module.local_size;
-        let window_size = self.surface_info.surface_resolution;
-        let invocation_x = window_size.width / u32::try_from(local_size.0).unwrap();
-        let invocation_y = window_size.height / u32::try_from(local_size.1).unwrap();
+        let invocation_x = render_resolution.width / u32::try_from(local_size.0).unwrap();
+        let invocation_y = render_resolution.height / u32::try_from(local_size.1).unwrap();
         let invocation_z = 1; // Hardcode for now.
 
-        self.device.cmd_dispatch(
-            **self.command_buffer,
-            invocation_x,
-            invocation_y,
-            invocation_z,
-        );
+        self.device
+            .cmd_dispatch(command_buffer, invocation_x, invocation_y, invocation_z);
     }
 
-    unsafe fn present(&self, present_index: usize) -> VResult<()> {
-        // TODO WHY DOES THIS WORK?!?!?! THIS MIGHT ACTUALLY CRASH?!
+    unsafe fn present(&mut self, target: usize, present_index: usize) -> VResult<()> {
+        let separate_present_queue = self.separate_present_queue();
+        let window = &mut self.targets[target];
+        let wait_semaphores = if separate_present_queue {
+            [**window.present_ready_semaphore]
+        } else {
+            [**window.compute_complete_semaphore]
+        };
+        let swapchains = [***window.swapchain()];
+        let image_indices = [u32::try_from(present_index).unwrap()];
         let present_info = vk::PresentInfoKHR::builder()
-            .wait_semaphores(&[**self.compute_complete_semaphore])
-            .swapchains(&[***self.swapchain()])
-            .image_indices(&[u32::try_from(present_index).unwrap()])
-            .build();
+            .wait_semaphores(&wait_semaphores)
+            .swapchains(&swapchains)
+            .image_indices(&image_indices);
 
-        Ok(self
+        match self
             .swapchain_loader
-            .queue_present(self.compute_queue, &present_info)
-            .map(|suboptimal| {
-                if suboptimal {
-                    warn!("Swapchain is suboptimal");
-                }
-            })?)
+            .queue_present(self.present_queue, &present_info)
+        {
+            Ok(suboptimal) => window.swapchain_outdated |= suboptimal,
+            Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => window.swapchain_outdated = true,
+            Err(err) => return Err(err.into()),
+        }
+        Ok(())
     }
 
     unsafe fn render_next_frame(
         &mut self,
+        target: usize,
         push_constant_values: &HashMap<String, Value>,
-    ) -> VResult<Option<Event>> {
-        let (present_index, present_image) = self.acquire_next_image()?;
-
-        self.reuse_command_buffer_fence.wait()?;
-        self.reuse_command_buffer_fence.reset()?;
-
-        self.begin_command_buffer()?;
-
-        // Transition image to "GENERAL" layout.
-        self.image_memory_barrier_layout_transition(
-            present_image,
-            vk::ImageLayout::PRESENT_SRC_KHR,
-            vk::ImageLayout::GENERAL,
-        );
+    ) -> VResult<()> {
+        let Some((present_index, present_image)) = self.acquire_next_image(target)? else {
+            return Ok(());
+        };
+
+        let window = &mut self.targets[target];
+        window.reuse_command_buffer_fence.wait()?;
+        window.reuse_command_buffer_fence.reset()?;
+        window.gpu_timer.collect()?;
+        window.pipeline_statistics.collect()?;
+
+        let window = &self.targets[target];
+        let command_buffer = **window.command_buffer;
+        self.begin_command_buffer(command_buffer)?;
+        window.gpu_timer.reset(&self.device, command_buffer);
+        window
+            .pipeline_statistics
+            .reset(&self.device, command_buffer);
+
+        // Transition image to "GENERAL" layout. Images owned by a separate present queue were
+        // not released back to the compute queue, so their contents are discarded. When blitting,
+        // shaders write to the intermediate image, which stays in "GENERAL" layout.
+        let blit = window.blit();
+        let old_layout = if self.separate_present_queue() {
+            vk::ImageLayout::UNDEFINED
+        } else {
+            vk::ImageLayout::PRESENT_SRC_KHR
+        };
+        if !blit {
+            self.image_memory_barrier_layout_transition(
+                command_buffer,
+                present_image,
+                old_layout,
+                vk::ImageLayout::GENERAL,
+            );
+        }
 
         // Prepare available fields.
         let mut push_constant_values = push_constant_values.clone();
@@ -611,48 +1753,119 @@ impl Vulkan {
             "frame_index".to_owned(),
             Value::U32(u32::try_from(self.num_frames).unwrap()),
         );
+        push_constant_values.insert(
+            "color_space".to_owned(),
+            Value::U32(window.surface_info.color_space as u32),
+        );
+        let render_resolution = window.surface_info.render_resolution;
+        let window_resolution = window.surface_info.surface_resolution;
+        for (name, value) in [
+            ("render_width", render_resolution.width),
+            ("render_height", render_resolution.height),
+            ("window_width", window_resolution.width),
+            ("window_height", window_resolution.height),
+        ] {
+            push_constant_values.insert(name.to_owned(), Value::U32(value));
+        }
+        if let Some(input) = &self.input {
+            window.insert_mouse_push_constants(&input.as_ref(), &mut push_constant_values);
+        }
 
-        for index in 0..self.shader_resources.len() {
-            let write_descriptor_set = self.shader_resources[index].get_write_descriptor_set(
-                &self.available_images,
-                &self.available_buffers,
-                &self.present_name,
-                present_index,
-                self.num_frames,
-            )?;
-            let resources = &self.shader_resources[index];
-
-            self.bind_pipeline(&resources.pipeline);
+        let expected_invocations =
+            u64::from(render_resolution.width) * u64::from(render_resolution.height);
+        let pinned_instances = self.pinned_instances();
+        for index in 0..window.shader_resources.len() {
+            let window = &mut self.targets[target];
+            window.shader_resources[index].pin_instances(&pinned_instances);
+            let (write_descriptor_set, descriptor_sets) = window.shader_resources[index]
+                .get_descriptor_sets(
+                    &self.device,
+                    &self.available_images,
+                    &self.available_buffers,
+                    &window.present_name,
+                    present_index,
+                    self.num_frames,
+                )?;
+            let window = &self.targets[target];
+            let resources = &window.shader_resources[index];
+
+            self.bind_pipeline(command_buffer, &resources.pipeline);
             self.push_constants(
+                command_buffer,
                 &resources.pipeline_layout,
                 &resources.shader_module,
                 &push_constant_values,
             );
-            self.push_descriptors(&resources.pipeline_layout, &write_descriptor_set);
-            self.dispatch(&resources.shader_module);
+            self.push_descriptors(
+                command_buffer,
+                &resources.pipeline_layout,
+                resources.descriptors.push_set(),
+                &write_descriptor_set,
+            );
+            self.bind_descriptor_sets(command_buffer, &resources.pipeline_layout, &descriptor_sets);
+            window
+                .gpu_timer
+                .begin_pass(&self.device, command_buffer, index);
+            window
+                .pipeline_statistics
+                .begin_pass(&self.device, command_buffer, index);
+            self.dispatch(command_buffer, &resources.shader_module, render_resolution);
+            let name = resources.shader_module.source_path.display().to_string();
+            let window = &mut self.targets[target];
+            window.pipeline_statistics.end_pass(
+                &self.device,
+                command_buffer,
+                index,
+                name.clone(),
+                expected_invocations,
+            );
+            window
+                .gpu_timer
+                .end_pass(&self.device, command_buffer, index, name);
         }
 
-        // Transition image to the "PRESENT_SRC" layout for presentation.
-        self.image_memory_barrier_layout_transition(
-            present_image,
-            vk::ImageLayout::GENERAL,
-            vk::ImageLayout::PRESENT_SRC_KHR,
-        );
-
-        self.end_command_buffer()?;
-        self.queue_submit_compute()?;
+        // Draw the overlay after upscaling, at window resolution.
+        if blit {
+            self.record_fsr(target, command_buffer, present_index)?;
+        }
+        let overlay = self.overlay.is_visible() && self.targets[target].overlay.is_some();
+        if overlay {
+            self.render_overlay(target, command_buffer, present_index)?;
+        }
+        if blit {
+            self.blit_to_swapchain(target, present_index, present_image, overlay);
+        }
 
-        // Present as soon as `compute_complete_semaphore` trips.
-        let present_result = self.present(present_index);
-        self.num_frames += 1;
+        // Transition image to the "PRESENT_SRC" layout for presentation, releasing it to the
+        // present queue if necessary.
+        let window = &self.targets[target];
+        if self.separate_present_queue() {
+            self.queue_family_ownership_barrier(
+                window,
+                command_buffer,
+                present_image,
+                window.swapchain_write_layout_and_stage().1,
+                vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+            );
+        } else if !blit {
+            self.image_memory_barrier_layout_transition(
+                command_buffer,
+                present_image,
+                vk::ImageLayout::GENERAL,
+                vk::ImageLayout::PRESENT_SRC_KHR,
+            );
+        }
 
-        if let Err(Error::Vk(vk::Result::ERROR_OUT_OF_DATE_KHR)) = present_result {
-            info!("Swapchain is out of date, resizing app");
-            self.reinitialize_swapchain()?;
-            Ok(Some(Event::Resized))
-        } else {
-            Ok(None)
+        self.end_command_buffer(command_buffer)?;
+        self.queue_submit_compute(target)?;
+        if self.separate_present_queue() {
+            self.queue_submit_present(target, present_index)?;
         }
+
+        // Present as soon as `compute_complete_semaphore` (or `present_ready_semaphore`) trips.
+        self.present(target, present_index)?;
+
+        Ok(())
     }
 
     pub fn wait_idle(&self) {
@@ -663,12 +1876,62 @@ impl Vulkan {
         }
     }
 
+    /// Render a frame to every window. Windows are rendered in the order they were added, each
+    /// with a separate submission, and see the same `frame_index`. Returns an event per window
+    /// whose swapchain was recreated.
     pub unsafe fn tick(
         &mut self,
         push_constant_values: &HashMap<String, Value>,
-    ) -> VResult<Option<Event>> {
+    ) -> VResult<Vec<Event>> {
+        // Recreate out of date swapchains and skip their frame, so the app can react to the new
+        // extent before rendering to them. Nothing is rendered to minimized windows.
+        let mut events = Vec::new();
+        let mut skipped = vec![false; self.targets.len()];
+        for (target, skipped) in skipped.iter_mut().enumerate() {
+            if !self.targets[target].swapchain_outdated {
+                continue;
+            }
+            *skipped = true;
+            let surface_resolution = self.current_surface_resolution(target)?;
+            if surface_resolution.width == 0 || surface_resolution.height == 0 {
+                continue;
+            }
+
+            debug!("Swapchain is out of date, resizing app");
+            let window = &mut self.targets[target];
+            window.swapchain_outdated = false;
+            self.reinitialize_target_swapchain(target)?;
+            self.redraw_requested.request();
+            let window = &self.targets[target];
+            let extent = window.surface_info.surface_resolution;
+            events.push(if target == 0 {
+                Event::Resized(extent)
+            } else {
+                Event::WindowResized(window.window_id, extent)
+            });
+        }
+        if skipped.iter().all(|skipped| *skipped) {
+            if events.is_empty() {
+                // Avoid spinning while there is nothing to present to.
+                std::thread::sleep(std::time::Duration::from_millis(10));
+            }
+            return Ok(events);
+        }
+
         self.transition_stale_images()?;
         self.recompile_shader_if_modified()?;
-        self.render_next_frame(push_constant_values)
+        self.update_overlay()?;
+        for (target, skipped) in skipped.into_iter().enumerate() {
+            if !skipped {
+                self.render_next_frame(target, push_constant_values)?;
+            }
+        }
+        self.num_frames += 1;
+        // The recreated windows are rendered next tick.
+        if events.is_empty() {
+            self.redraw_requested.clear();
+        }
+        self.instance.check_validation_errors();
+        Ok(events)
     }
 }
@@ -1,15 +1,18 @@
-use std::{ffi::c_void, ops::Deref, rc::Rc};
+use std::{cell::Cell, ffi::c_void, mem, ops::Deref, ptr, rc::Rc};
 
 use log::debug;
 
-use crate::error::VResult;
+use crate::error::{Error, VResult};
 
 use super::{
     resources::{
-        buffer::{Buffer, BufferUsage}, device::Device, device_memory::DeviceMemory, memory_mapping::MemoryMapping,
+        buffer::{Buffer, BufferUsage},
+        device::Device,
+        device_memory::DeviceMemory,
+        memory_mapping::MemoryMapping,
         physical_device::PhysicalDevice,
     },
-    Vulkan,
+    RedrawRequest, Vulkan,
 };
 
 #[allow(clippy::module_name_repetitions)]
@@ -47,13 +50,18 @@ impl MultiBufferUnit {
 /// A buffer is composed of multiple device buffers used for multi-buffering (i.e.
 /// triple-buffering). These buffers are automatically mapped to system memory to be written to,
 /// and unmapped when the object is dropped.
-pub struct MultiBuffer(Vec<MultiBufferUnit>);
+pub struct MultiBuffer {
+    units: Vec<MultiBufferUnit>,
+    latest_written: Cell<Option<usize>>,
+    /// Shared with `Vulkan`, set on writes so on-demand rendering redraws.
+    redraw_requested: Rc<RedrawRequest>,
+}
 
 impl Deref for MultiBuffer {
     type Target = [MultiBufferUnit];
 
     fn deref(&self) -> &Self::Target {
-        &self.0
+        &self.units
     }
 }
 
@@ -64,18 +72,61 @@ impl MultiBuffer {
         usage: BufferUsage,
         size: usize,
         num_buffers: usize,
+        redraw_requested: &Rc<RedrawRequest>,
     ) -> VResult<Rc<Self>> {
         debug!("Creating buffer of size {}", size);
         let buffers = (0..num_buffers)
             .map(|_| MultiBufferUnit::new(physical_device, device, usage, size))
             .collect::<VResult<Vec<_>>>()?;
-        Ok(Rc::new(MultiBuffer(buffers)))
+        Ok(Rc::new(MultiBuffer {
+            units: buffers,
+            latest_written: Cell::new(None),
+            redraw_requested: redraw_requested.clone(),
+        }))
     }
 
     #[must_use]
     pub fn mapped(&self, index: usize) -> *mut c_void {
         **self[index].mapping
     }
+
+    /// The instance shaders read in frame `frame_index` (see `Vulkan::num_frames`), unless the
+    /// binding is pinned using `Vulkan::select_instance`.
+    #[must_use]
+    pub fn current_index(&self, frame_index: usize) -> usize {
+        frame_index % self.len()
+    }
+
+    /// Copy `data` to the start of the instance read in frame `frame_index`.
+    pub fn write_current<T: Copy>(&self, frame_index: usize, data: &[T]) -> VResult<()> {
+        let index = self.current_index(frame_index);
+        let size = mem::size_of_val(data);
+        if size > self[index].buffer.size {
+            let msg = format!(
+                "Writing {size} bytes to a buffer of {} bytes",
+                self[index].buffer.size
+            );
+            return Err(Error::Local(msg));
+        }
+
+        unsafe {
+            ptr::copy_nonoverlapping(data.as_ptr().cast::<u8>(), self.mapped(index).cast(), size);
+        }
+        self.mark_written(index);
+        Ok(())
+    }
+
+    /// Record a write to instance `index` done through `mapped`, see
+    /// `InstanceSelection::LatestWritten`. Also requests a redraw, see `Vulkan::needs_redraw`.
+    pub fn mark_written(&self, index: usize) {
+        self.latest_written.set(Some(index));
+        self.redraw_requested.request();
+    }
+
+    #[must_use]
+    pub fn latest_written(&self) -> Option<usize> {
+        self.latest_written.get()
+    }
 }
 
 impl Drop for MultiBuffer {
@@ -93,8 +144,21 @@ impl Vulkan {
         num_buffers: Option<usize>,
     ) -> VResult<Rc<MultiBuffer>> {
         unsafe {
-            let num_buffers = num_buffers.unwrap_or(self.surface_info.desired_image_count);
-            let buffer = MultiBuffer::new(&self.physical_device, &self.device, usage, size, num_buffers)?;
+            let num_buffers = num_buffers.unwrap_or(self.surface_info().desired_image_count);
+            let buffer = MultiBuffer::new(
+                &self.physical_device,
+                &self.device,
+                usage,
+                size,
+                num_buffers,
+                &self.redraw_requested,
+            )?;
+            for (index, unit) in buffer.iter().enumerate() {
+                let unit_name = format!("{name}[{index}]");
+                self.set_object_name(**unit.buffer, &unit_name);
+                self.set_object_name(**unit.memory, &unit_name);
+            }
+
             let buffers = buffer
                 .iter()
                 .map(|unit| unit.buffer.clone())
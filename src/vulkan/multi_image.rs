@@ -1,9 +1,9 @@
-use std::{ops::Deref, rc::Rc};
+use std::{mem, ops::Deref, rc::Rc};
 
 use ash::vk;
 use log::debug;
 
-use crate::error::VResult;
+use crate::error::{Error, VResult};
 
 use super::{
     resources::{
@@ -13,6 +13,78 @@ use super::{
     Vulkan,
 };
 
+/// How the size of an image is determined. Screen-relative images follow the render resolution
+/// and are recreated, and re-registered, whenever the swapchain is.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SizePolicy {
+    Screen,
+    ScreenScaled(f32),
+    Fixed(vk::Extent2D),
+}
+
+impl SizePolicy {
+    #[must_use]
+    pub fn extent(self, screen: vk::Extent2D) -> vk::Extent2D {
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let scale = |value: u32, factor: f32| ((value as f32 * factor).round() as u32).max(1);
+        match self {
+            Self::Screen => screen,
+            Self::ScreenScaled(factor) => vk::Extent2D {
+                width: scale(screen.width, factor),
+                height: scale(screen.height, factor),
+            },
+            Self::Fixed(extent) => extent,
+        }
+    }
+
+    #[must_use]
+    pub fn is_screen_relative(self) -> bool {
+        !matches!(self, Self::Fixed(..))
+    }
+}
+
+/// An image created by `new_multi_image` and everything needed to recreate it.
+pub(super) struct ManagedImage {
+    name: String,
+    format: vk::Format,
+    size: SizePolicy,
+    num_images: Option<usize>,
+    image: Rc<MultiImage>,
+    /// Names registered using `shift`, with their offset.
+    shifted_names: Vec<(String, usize)>,
+    /// Clear the image when (re)created, used for histories.
+    clear: bool,
+}
+
+/// Name of the history image `age` frames ago: `name`, `name_prev`, `name_prev2`, ...
+fn history_name(name: &str, age: usize) -> String {
+    match age {
+        0 => name.to_owned(),
+        1 => format!("{name}_prev"),
+        _ => format!("{name}_prev{age}"),
+    }
+}
+
+/// The images of the last `N` frames, see `Vulkan::new_history`. The images rotate with the
+/// frame index, independent of the number of swapchain images.
+pub struct History<const N: usize> {
+    name: String,
+}
+
+impl<const N: usize> History<N> {
+    #[must_use]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The binding name of the image `age` frames ago, `age` must be less than `N`.
+    #[must_use]
+    pub fn binding_name(&self, age: usize) -> String {
+        assert!(age < N, "History {} only holds {N} frames", self.name);
+        history_name(&self.name, age)
+    }
+}
+
 #[allow(clippy::module_name_repetitions)]
 #[derive(Clone)]
 pub struct MultiImageUnit {
@@ -82,6 +154,13 @@ impl MultiImage {
             .collect::<VResult<Vec<_>>>()?;
         Ok(Rc::new(Self(images)))
     }
+
+    /// The instance shaders access in frame `frame_index` (see `Vulkan::num_frames`), unless the
+    /// binding is pinned using `Vulkan::select_instance`.
+    #[must_use]
+    pub fn current_index(&self, frame_index: usize) -> usize {
+        frame_index % self.len()
+    }
 }
 
 impl Drop for MultiImage {
@@ -91,7 +170,89 @@ impl Drop for MultiImage {
 }
 
 impl Vulkan {
+    /// Create and register an image. Screen-relative images are replaced on swapchain
+    /// recreation, use `multi_image` to get the current one.
     pub fn new_multi_image(
+        &mut self,
+        name: &str,
+        format: vk::Format,
+        size: SizePolicy,
+        num_images: Option<usize>,
+    ) -> VResult<Rc<MultiImage>> {
+        self.new_managed_image(name, format, size, num_images, false)
+    }
+
+    fn new_managed_image(
+        &mut self,
+        name: &str,
+        format: vk::Format,
+        size: SizePolicy,
+        num_images: Option<usize>,
+        clear: bool,
+    ) -> VResult<Rc<MultiImage>> {
+        let extent = size.extent(self.surface_info().render_resolution);
+        let image = self.create_multi_image(name, format, extent, num_images)?;
+        if clear {
+            self.images_to_clear
+                .extend(image.iter().map(|unit| unit.image.clone()));
+        }
+
+        self.managed_images.retain(|entry| entry.name != name);
+        self.managed_images.push(ManagedImage {
+            name: name.to_owned(),
+            format,
+            size,
+            num_images,
+            image: image.clone(),
+            shifted_names: Vec::new(),
+            clear,
+        });
+
+        Ok(image)
+    }
+
+    /// Create an image holding the last `N` frames, bound as `name` for the current frame and
+    /// `name_prev`, `name_prev2`, ... for the previous ones. Histories are cleared on creation
+    /// and whenever they are resized.
+    pub fn new_history<const N: usize>(
+        &mut self,
+        name: &str,
+        format: vk::Format,
+        size: SizePolicy,
+    ) -> VResult<History<N>> {
+        if N == 0 {
+            return Err(Error::Local(format!(
+                "History {name} needs at least one frame"
+            )));
+        }
+        let image = self.new_managed_image(name, format, size, Some(N), true)?;
+        for age in 1..N {
+            self.shift(&image, &history_name(name, age), age);
+        }
+        Ok(History {
+            name: name.to_owned(),
+        })
+    }
+
+    /// Clear all images of `history`, e.g. after a camera cut.
+    pub fn reset_history<const N: usize>(&mut self, history: &History<N>) {
+        if let Some(image) = self.multi_image(&history.name) {
+            self.images_to_clear
+                .extend(image.iter().map(|unit| unit.image.clone()));
+        }
+    }
+
+    /// The current instance of the image `name` created by `new_multi_image`.
+    #[must_use]
+    pub fn multi_image(&self, name: &str) -> Option<Rc<MultiImage>> {
+        self.managed_images
+            .iter()
+            .find(|entry| entry.name == name)
+            .map(|entry| entry.image.clone())
+    }
+
+    /// Create and register an image, without recreating it on resize.
+    pub(super) fn create_multi_image(
         &mut self,
         name: &str,
         format: vk::Format,
@@ -99,7 +260,7 @@ impl Vulkan {
         num_images: Option<usize>,
     ) -> VResult<Rc<MultiImage>> {
         unsafe {
-            let num_images = num_images.unwrap_or(self.surface_info.desired_image_count);
+            let num_images = num_images.unwrap_or(self.surface_info().desired_image_count);
             let image = MultiImage::new(
                 &self.physical_device,
                 &self.device,
@@ -109,6 +270,13 @@ impl Vulkan {
                 num_images,
             )?;
 
+            for (index, image_unit) in image.iter().enumerate() {
+                let unit_name = format!("{name}[{index}]");
+                self.set_object_name(**image_unit.image, &unit_name);
+                self.set_object_name(**image_unit.view, &unit_name);
+                self.set_object_name(**image_unit.memory, &unit_name);
+            }
+
             for image_unit in image.iter() {
                 self.stale_images.push((
                     name.to_owned(),
@@ -128,11 +296,117 @@ impl Vulkan {
         }
     }
 
+    /// Recreate all screen-relative images with the current render resolution, keeping their
+    /// shifted aliases and bindless table entries.
+    pub(super) fn recreate_screen_sized_images(&mut self) -> VResult<()> {
+        let (screen_sized_images, fixed_images) =
+            mem::take(&mut self.managed_images)
+                .into_iter()
+                .partition::<Vec<_>, _>(|entry| entry.size.is_screen_relative());
+        self.managed_images = fixed_images;
+
+        for entry in screen_sized_images {
+            self.stale_images.retain(|(name, ..)| *name != entry.name);
+            let image = self.new_managed_image(
+                &entry.name,
+                entry.format,
+                entry.size,
+                entry.num_images,
+                entry.clear,
+            )?;
+            for (shifted_name, offset) in &entry.shifted_names {
+                self.shift(&image, shifted_name, *offset);
+            }
+
+            let tables = self
+                .bindless_tables
+                .iter_mut()
+                .filter_map(|(table, images)| {
+                    let mut replaced = false;
+                    for table_image in images.iter_mut() {
+                        if Rc::ptr_eq(table_image, &entry.image) {
+                            *table_image = image.clone();
+                            replaced = true;
+                        }
+                    }
+                    replaced.then(|| (table.clone(), images.clone()))
+                })
+                .collect::<Vec<_>>();
+            for (table, images) in tables {
+                self.register_multi_image_array(&table, &images);
+            }
+        }
+        Ok(())
+    }
+
+    /// Forget the images registered as `names` and `images`, including their shifted aliases,
+    /// pending transitions and clears, instance selections and bindless table entries.
+    pub(super) fn unregister_images(&mut self, names: &[String], images: &[Rc<MultiImage>]) {
+        let mut names = names.to_vec();
+        for entry in &self.managed_images {
+            if names.contains(&entry.name) {
+                names.extend(entry.shifted_names.iter().map(|(alias, _)| alias.clone()));
+            }
+        }
+        self.managed_images
+            .retain(|entry| !names.contains(&entry.name));
+        self.stale_images.retain(|(name, ..)| !names.contains(name));
+        for name in &names {
+            self.available_images.remove(name);
+            self.instance_selections.remove(name);
+        }
+
+        let is_removed =
+            |image: &Rc<MultiImage>| images.iter().any(|other| Rc::ptr_eq(image, other));
+        self.images_to_clear.retain(|image| {
+            !images
+                .iter()
+                .flat_map(|multi_image| multi_image.iter())
+                .any(|unit| Rc::ptr_eq(&unit.image, image))
+        });
+        // Later entries of a table move down, shifting their IDs.
+        let tables = self
+            .bindless_tables
+            .iter_mut()
+            .filter(|(_, table_images)| table_images.iter().any(is_removed))
+            .map(|(table, table_images)| {
+                table_images.retain(|image| !is_removed(image));
+                (table.clone(), table_images.clone())
+            })
+            .collect::<Vec<_>>();
+        for (table, table_images) in tables {
+            if table_images.is_empty() {
+                self.bindless_tables.remove(&table);
+                self.available_images.remove(&table);
+            } else {
+                self.register_multi_image_array(&table, &table_images);
+            }
+        }
+    }
+
+    /// Register `multi_image` rotated by one instance, i.e. the instance of the previous frame.
+    /// Prefer `new_history`, which doesn't depend on the number of instances.
     pub fn prev_shift(&mut self, multi_image: &MultiImage, name: &str) -> Rc<MultiImage> {
-        let last_index = multi_image.len() - 1;
-        let reordered_images = multi_image[last_index..]
+        self.shift(multi_image, name, 1)
+    }
+
+    /// Register `multi_image` rotated by `offset` instances, so that in every frame it binds the
+    /// instance bound `offset` frames ago.
+    fn shift(&mut self, multi_image: &MultiImage, name: &str, offset: usize) -> Rc<MultiImage> {
+        if let Some(entry) = self
+            .managed_images
+            .iter_mut()
+            .find(|entry| std::ptr::eq(&*entry.image, multi_image))
+        {
+            if !entry.shifted_names.iter().any(|(alias, _)| alias == name) {
+                entry.shifted_names.push((name.to_owned(), offset));
+            }
+        }
+
+        let split_index = multi_image.len() - offset % multi_image.len();
+        let reordered_images = multi_image[split_index..]
             .iter()
-            .chain(multi_image[..last_index].iter())
+            .chain(multi_image[..split_index].iter())
             .cloned()
             .collect();
         let multi_image = Rc::new(MultiImage(reordered_images));
@@ -149,3 +423,36 @@ impl Vulkan {
         multi_image
     }
 }
+
+impl Vulkan {
+    /// Register multiple images under a single name, to be bound as a descriptor array, e.g.
+    /// `uniform sampler2D textures[4]`. Instance `i` of the array holds instance `i` of each image.
+    pub fn register_multi_image_array(&mut self, name: &str, images: &[Rc<MultiImage>]) {
+        let num_instances = images.iter().map(|image| image.len()).max().unwrap_or(1);
+        let instances = (0..num_instances)
+            .map(|index| {
+                images
+                    .iter()
+                    .map(|image| {
+                        let unit = &image[index % image.len()];
+                        (unit.view.clone(), self.sampler.clone())
+                    })
+                    .collect()
+            })
+            .collect::<Vec<_>>();
+        self.register_image_array(name, &instances);
+    }
+
+    /// Append an image to the bindless table `table` and return its index. Shaders declare the
+    /// table as a runtime-sized array, e.g. `uniform sampler2D textures[]`, and index it using
+    /// the returned ID. Declared as the last binding of a set other than the one of the present
+    /// image, e.g. `layout(set = 1, binding = 0)`, the table is sized by the number of images
+    /// instead of `RUNTIME_ARRAY_CAPACITY`.
+    pub fn add_bindless_image(&mut self, table: &str, image: &Rc<MultiImage>) -> u32 {
+        let images = self.bindless_tables.entry(table.to_owned()).or_default();
+        images.push(image.clone());
+        let images = images.clone();
+        self.register_multi_image_array(table, &images);
+        u32::try_from(images.len() - 1).unwrap()
+    }
+}
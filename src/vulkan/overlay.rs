@@ -0,0 +1,550 @@
+use std::{collections::HashMap, path::Path, ptr, rc::Rc};
+
+use ash::vk;
+use log::{error, info};
+use winit::event::VirtualKeyCode;
+
+use crate::{error::VResult, event_loop::FrameStats};
+
+use super::{
+    fsr,
+    multi_buffer::MultiBuffer,
+    resources::{buffer::BufferUsage, surface_info::SurfaceInfo},
+    ShaderResources, Value, Vulkan,
+};
+
+/// Characters per line of the overlay.
+pub const COLUMNS: usize = 128;
+/// Lines of the overlay, the text below is cut off.
+pub const ROWS: usize = 48;
+
+/// Name of the buffer holding the overlay text, one byte per character.
+const TEXT_BUFFER: &str = "overlay_text";
+
+/// Window height per text scale step, i.e. the text is drawn twice as large at 1080p.
+const SCALE_HEIGHT: u32 = 540;
+
+/// 5x7 glyphs of the printable ASCII characters from `' '` to `'~'`, rows from top to bottom.
+const GLYPHS: [&str; 95] = [
+    "..... ..... ..... ..... ..... ..... .....", // ' '
+    "..#.. ..#.. ..#.. ..#.. ..#.. ..... ..#..", // '!'
+    ".#.#. .#.#. .#.#. ..... ..... ..... .....", // '"'
+    ".#.#. .#.#. ##### .#.#. ##### .#.#. .#.#.", // '#'
+    "..#.. .#### #.#.. .###. ..#.# ####. ..#..", // '$'
+    "##... ##..# ...#. ..#.. .#... #..## ...##", // '%'
+    ".##.. #..#. #.#.. .#... #.#.# #..#. .##.#", // '&'
+    "..#.. ..#.. .#... ..... ..... ..... .....", // '''
+    "...#. ..#.. .#... .#... .#... ..#.. ...#.", // '('
+    ".#... ..#.. ...#. ...#. ...#. ..#.. .#...", // ')'
+    "..... ..#.. #.#.# .###. #.#.# ..#.. .....", // '*'
+    "..... ..#.. ..#.. ##### ..#.. ..#.. .....", // '+'
+    "..... ..... ..... ..... .##.. ..#.. .#...", // ','
+    "..... ..... ..... ##### ..... ..... .....", // '-'
+    "..... ..... ..... ..... ..... .##.. .##..", // '.'
+    "..... ....# ...#. ..#.. .#... #.... .....", // '/'
+    ".###. #...# #..## #.#.# ##..# #...# .###.", // '0'
+    "..#.. .##.. ..#.. ..#.. ..#.. ..#.. .###.", // '1'
+    ".###. #...# ....# ...#. ..#.. .#... #####", // '2'
+    "##### ...#. ..#.. ...#. ....# #...# .###.", // '3'
+    "...#. ..##. .#.#. #..#. ##### ...#. ...#.", // '4'
+    "##### #.... ####. ....# ....# #...# .###.", // '5'
+    "..##. .#... #.... ####. #...# #...# .###.", // '6'
+    "##### ....# ...#. ..#.. .#... .#... .#...", // '7'
+    ".###. #...# #...# .###. #...# #...# .###.", // '8'
+    ".###. #...# #...# .#### ....# ...#. .##..", // '9'
+    "..... .##.. .##.. ..... .##.. .##.. .....", // ':'
+    "..... .##.. .##.. ..... .##.. ..#.. .#...", // ';'
+    "...#. ..#.. .#... #.... .#... ..#.. ...#.", // '<'
+    "..... ..... ##### ..... ##### ..... .....", // '='
+    ".#... ..#.. ...#. ....# ...#. ..#.. .#...", // '>'
+    ".###. #...# ....# ...#. ..#.. ..... ..#..", // '?'
+    ".###. #...# ....# .##.# #.#.# #.#.# .###.", // '@'
+    ".###. #...# #...# ##### #...# #...# #...#", // 'A'
+    "####. #...# #...# ####. #...# #...# ####.", // 'B'
+    ".###. #...# #.... #.... #.... #...# .###.", // 'C'
+    "###.. #..#. #...# #...# #...# #..#. ###..", // 'D'
+    "##### #.... #.... ####. #.... #.... #####", // 'E'
+    "##### #.... #.... ####. #.... #.... #....", // 'F'
+    ".###. #...# #.... #.### #...# #...# .####", // 'G'
+    "#...# #...# #...# ##### #...# #...# #...#", // 'H'
+    ".###. ..#.. ..#.. ..#.. ..#.. ..#.. .###.", // 'I'
+    "..### ...#. ...#. ...#. ...#. #..#. .##..", // 'J'
+    "#...# #..#. #.#.. ##... #.#.. #..#. #...#", // 'K'
+    "#.... #.... #.... #.... #.... #.... #####", // 'L'
+    "#...# ##.## #.#.# #.#.# #...# #...# #...#", // 'M'
+    "#...# #...# ##..# #.#.# #..## #...# #...#", // 'N'
+    ".###. #...# #...# #...# #...# #...# .###.", // 'O'
+    "####. #...# #...# ####. #.... #.... #....", // 'P'
+    ".###. #...# #...# #...# #.#.# #..#. .##.#", // 'Q'
+    "####. #...# #...# ####. #.#.. #..#. #...#", // 'R'
+    ".#### #.... #.... .###. ....# ....# ####.", // 'S'
+    "##### ..#.. ..#.. ..#.. ..#.. ..#.. ..#..", // 'T'
+    "#...# #...# #...# #...# #...# #...# .###.", // 'U'
+    "#...# #...# #...# #...# #...# .#.#. ..#..", // 'V'
+    "#...# #...# #...# #.#.# #.#.# #.#.# .#.#.", // 'W'
+    "#...# #...# .#.#. ..#.. .#.#. #...# #...#", // 'X'
+    "#...# #...# #...# .#.#. ..#.. ..#.. ..#..", // 'Y'
+    "##### ....# ...#. ..#.. .#... #.... #####", // 'Z'
+    ".###. .#... .#... .#... .#... .#... .###.", // '['
+    "..... #.... .#... ..#.. ...#. ....# .....", // '\'
+    ".###. ...#. ...#. ...#. ...#. ...#. .###.", // ']'
+    "..#.. .#.#. #...# ..... ..... ..... .....", // '^'
+    "..... ..... ..... ..... ..... ..... #####", // '_'
+    ".#... ..#.. ...#. ..... ..... ..... .....", // '`'
+    "..... ..... .###. ....# .#### #...# .####", // 'a'
+    "#.... #.... #.##. ##..# #...# #...# ####.", // 'b'
+    "..... ..... .###. #.... #.... #...# .###.", // 'c'
+    "....# ....# .##.# #..## #...# #...# .####", // 'd'
+    "..... ..... .###. #...# ##### #.... .###.", // 'e'
+    "..##. .#..# .#... ###.. .#... .#... .#...", // 'f'
+    "..... .#### #...# #...# .#### ....# .###.", // 'g'
+    "#.... #.... #.##. ##..# #...# #...# #...#", // 'h'
+    "..#.. ..... .##.. ..#.. ..#.. ..#.. .###.", // 'i'
+    "...#. ..... ..##. ...#. ...#. #..#. .##..", // 'j'
+    "#.... #.... #..#. #.#.. ##... #.#.. #..#.", // 'k'
+    ".##.. ..#.. ..#.. ..#.. ..#.. ..#.. .###.", // 'l'
+    "..... ..... ##.#. #.#.# #.#.# #...# #...#", // 'm'
+    "..... ..... #.##. ##..# #...# #...# #...#", // 'n'
+    "..... ..... .###. #...# #...# #...# .###.", // 'o'
+    "..... ####. #...# #...# ####. #.... #....", // 'p'
+    "..... .#### #...# #...# .#### ....# ....#", // 'q'
+    "..... ..... #.##. ##..# #.... #.... #....", // 'r'
+    "..... ..... .#### #.... .###. ....# ####.", // 's'
+    ".#... .#... ###.. .#... .#... .#..# ..##.", // 't'
+    "..... ..... #...# #...# #...# #..## .##.#", // 'u'
+    "..... ..... #...# #...# #...# .#.#. ..#..", // 'v'
+    "..... ..... #...# #...# #.#.# #.#.# .#.#.", // 'w'
+    "..... ..... #...# .#.#. ..#.. .#.#. #...#", // 'x'
+    "..... #...# #...# #...# .#### ....# .###.", // 'y'
+    "..... ..... ##### ...#. ..#.. .#... #####", // 'z'
+    "...#. ..#.. ..#.. .#... ..#.. ..#.. ...#.", // '{'
+    "..#.. ..#.. ..#.. ..#.. ..#.. ..#.. ..#..", // '|'
+    ".#... ..#.. ..#.. ...#. ..#.. ..#.. .#...", // '}'
+    "..... ..... .#... #.#.# ...#. ..... .....", // '~'
+];
+
+/// Draws the text in `overlay_text` with a black outline on top of `PRESENT_IMAGE`, which is
+/// replaced by the name of the image the overlay is drawn on.
+const SHADER: &str = r"#version 450
+
+layout(local_size_x = 8, local_size_y = 8) in;
+
+layout(push_constant, std140) uniform PushConstants {
+    layout(offset = 0) uint overlay_columns;
+    layout(offset = 4) uint overlay_rows;
+    layout(offset = 8) uint overlay_scale;
+} constants;
+
+layout(rgba32f, binding = 0) uniform image2D PRESENT_IMAGE;
+
+layout(std430, binding = 1) readonly buffer OverlayText {
+    uint chars[];
+} overlay_text;
+
+// Two words per glyph, bit `5 * row + column` is set where the glyph is drawn.
+const uint FONT[190] = uint[](FONT_WORDS);
+
+const ivec2 GLYPH_SIZE = ivec2(5, 7);
+const ivec2 CELL_SIZE = ivec2(6, 9);
+const ivec2 MARGIN = ivec2(4, 4);
+
+// Whether the text covers `position`, in unscaled pixels relative to the first character.
+bool covered(ivec2 position) {
+    if (position.x < 0 || position.y < 0) {
+        return false;
+    }
+    ivec2 cell = position / CELL_SIZE;
+    if (cell.x >= int(constants.overlay_columns) || cell.y >= int(constants.overlay_rows)) {
+        return false;
+    }
+    ivec2 glyph_position = position - cell * CELL_SIZE;
+    if (glyph_position.x >= GLYPH_SIZE.x || glyph_position.y >= GLYPH_SIZE.y) {
+        return false;
+    }
+
+    uint index = uint(cell.y) * constants.overlay_columns + uint(cell.x);
+    uint character = (overlay_text.chars[index / 4] >> (8 * (index % 4))) & 0xff;
+    if (character <= 32 || character > 126) {
+        return false;
+    }
+    uint bit = uint(glyph_position.y * GLYPH_SIZE.x + glyph_position.x);
+    uint word = FONT[2 * (character - 32) + bit / 32];
+    return ((word >> (bit % 32)) & 1) != 0;
+}
+
+void main() {
+    ivec2 pixel = ivec2(gl_GlobalInvocationID.xy);
+    ivec2 position = pixel / int(constants.overlay_scale) - MARGIN;
+
+    if (covered(position)) {
+        imageStore(PRESENT_IMAGE, pixel, vec4(1.0));
+        return;
+    }
+    // Outline the text, so it is readable on any background.
+    for (int y = -1; y <= 1; y++) {
+        for (int x = -1; x <= 1; x++) {
+            if (covered(position + ivec2(x, y))) {
+                imageStore(PRESENT_IMAGE, pixel, vec4(0.0, 0.0, 0.0, 1.0));
+                return;
+            }
+        }
+    }
+}
+";
+
+/// The glyphs as GLSL `uint` literals, two per glyph.
+fn font_words() -> String {
+    GLYPHS
+        .iter()
+        .flat_map(|glyph| {
+            let bits = glyph
+                .chars()
+                .filter(|c| *c != ' ')
+                .enumerate()
+                .filter(|(_, c)| *c == '#')
+                .fold(0u64, |bits, (index, _)| bits | (1 << index));
+            #[allow(clippy::cast_possible_truncation)]
+            [bits as u32, (bits >> 32) as u32]
+        })
+        .map(|word| format!("{word:#x}u"))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Wrap `lines` to `COLUMNS` and pack them into `ROWS` lines of `COLUMNS` bytes, four per
+/// word. Characters without a glyph are replaced by `?`.
+fn layout_text(lines: &[String]) -> Vec<u32> {
+    let mut chars = vec![0u8; COLUMNS * ROWS];
+    let rows = lines.iter().flat_map(|line| {
+        let line = line
+            .chars()
+            .map(|c| match c {
+                '\t' => b' ',
+                ' '..='~' => c as u8,
+                _ => b'?',
+            })
+            .collect::<Vec<_>>();
+        // Keep empty lines.
+        let mut rows = line.chunks(COLUMNS).map(<[u8]>::to_vec).collect::<Vec<_>>();
+        if rows.is_empty() {
+            rows.push(Vec::new());
+        }
+        rows
+    });
+    for (row, text) in chars.chunks_mut(COLUMNS).zip(rows) {
+        row[..text.len()].copy_from_slice(&text);
+    }
+    chars
+        .chunks(4)
+        .map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]]))
+        .collect()
+}
+
+/// On-screen statistics and shader errors, see `Vulkan::toggle_overlay`.
+pub(super) struct Overlay {
+    visible: bool,
+    key: Option<VirtualKeyCode>,
+    frame_stats: FrameStats,
+    text: Option<Rc<MultiBuffer>>,
+}
+
+impl Overlay {
+    pub(super) fn new(visible: bool, key: Option<VirtualKeyCode>) -> Self {
+        Self {
+            visible,
+            key,
+            frame_stats: FrameStats::default(),
+            text: None,
+        }
+    }
+
+    pub(super) fn is_visible(&self) -> bool {
+        self.visible
+    }
+}
+
+/// Name of the window-sized image the overlay of present image `present_name` is drawn on when
+/// it is upscaled without FSR.
+pub(super) fn image_name(present_name: &str) -> String {
+    format!("{present_name}_overlay")
+}
+
+/// Source of the overlay pass drawing to the image `image_name`.
+pub(super) fn shader_source(image_name: &str) -> String {
+    SHADER
+        .replace("PRESENT_IMAGE", image_name)
+        .replace("FONT_WORDS", &font_words())
+}
+
+/// Push constants of the overlay pass.
+pub(super) fn push_constants(surface_info: &SurfaceInfo) -> HashMap<String, Value> {
+    let scale = (surface_info.surface_resolution.height / SCALE_HEIGHT).max(1);
+    [
+        ("overlay_columns", COLUMNS),
+        ("overlay_rows", ROWS),
+        ("overlay_scale", usize::try_from(scale).unwrap()),
+    ]
+    .into_iter()
+    .map(|(name, value)| (name.to_owned(), Value::U32(u32::try_from(value).unwrap())))
+    .collect()
+}
+
+impl Vulkan {
+    /// Show or hide the overlay with the FPS, the GPU timings and shader compilation errors. Also
+    /// bound to `VulkanConfig::overlay_key` if `set_input` was called.
+    pub fn toggle_overlay(&mut self) {
+        self.set_overlay_visible(!self.overlay.visible);
+    }
+
+    pub fn set_overlay_visible(&mut self, visible: bool) {
+        info!("{} overlay", if visible { "Showing" } else { "Hiding" });
+        self.overlay.visible = visible;
+        self.redraw_requested.request();
+    }
+
+    #[must_use]
+    pub fn overlay_visible(&self) -> bool {
+        self.overlay.visible
+    }
+
+    /// Frame timing shown by the overlay, e.g. from `App::frame_finished`.
+    pub fn set_frame_stats(&mut self, stats: &FrameStats) {
+        self.overlay.frame_stats = *stats;
+    }
+
+    fn overlay_text(&self) -> Vec<String> {
+        let stats = &self.overlay.frame_stats;
+        let mut lines = vec![format!(
+            "{:.1} fps ({:.2} ms)",
+            stats.fps(),
+            stats.average_frame_time * 1000f32
+        )];
+
+        for (name, milliseconds) in self.gpu_timings() {
+            let name = Path::new(name)
+                .file_name()
+                .map_or(name.into(), |file_name| file_name.to_string_lossy());
+            lines.push(format!("{name:<32} {milliseconds:>8.3} ms"));
+        }
+
+        let mut shader_errors = self.shader_errors.iter().collect::<Vec<_>>();
+        shader_errors.sort();
+        for (path, error) in shader_errors {
+            lines.push(String::new());
+            lines.push(format!("Error in {path}"));
+            lines.extend(error.lines().map(str::to_owned));
+        }
+        lines
+    }
+
+    /// Toggle the overlay on key press, create its resources when it is first shown and update
+    /// its text. Called once per tick.
+    pub(super) unsafe fn update_overlay(&mut self) -> VResult<()> {
+        if let (Some(input), Some(key)) = (&self.input, self.overlay.key) {
+            if input.as_ref().just_pressed(key) {
+                self.toggle_overlay();
+            }
+        }
+        if !self.overlay.visible {
+            return Ok(());
+        }
+
+        if self.overlay.text.is_none() {
+            let text =
+                self.new_multi_buffer(TEXT_BUFFER, BufferUsage::Storage, COLUMNS * ROWS, None)?;
+            self.overlay.text = Some(text);
+        }
+        for target in 0..self.targets.len() {
+            if self.targets[target].overlay.is_some() {
+                continue;
+            }
+            // Draw on the upscaled output, so the text stays sharp at any render resolution.
+            let window = &self.targets[target];
+            let present_name = window.present_name.clone();
+            let surface_info = &window.surface_info;
+            let image_name = if window.fsr.is_some() {
+                let [_, upscaled_name] = fsr::image_names(&present_name);
+                upscaled_name
+            } else if window.blit()
+                && surface_info.render_resolution != surface_info.surface_resolution
+            {
+                let image_name = image_name(&present_name);
+                let surface_resolution = surface_info.surface_resolution;
+                let image =
+                    self.create_multi_image(&image_name, fsr::FORMAT, surface_resolution, Some(1))?;
+                self.targets[target].overlay_image = Some(image);
+                image_name
+            } else {
+                present_name.clone()
+            };
+            match ShaderResources::from_source(
+                &self.device,
+                Path::new("overlay.comp"),
+                &shader_source(&image_name),
+                &present_name,
+            ) {
+                Ok(resources) => self.targets[target].overlay = Some(resources),
+                Err(err) => {
+                    error!("Overlay unavailable: {err}");
+                    self.overlay.visible = false;
+                    return Ok(());
+                }
+            }
+        }
+
+        // Written without `MultiBuffer::write_current`, which would request a redraw every frame.
+        let text = layout_text(&self.overlay_text());
+        if let Some(buffer) = &self.overlay.text {
+            let index = buffer.current_index(self.num_frames);
+            ptr::copy_nonoverlapping(text.as_ptr(), buffer.mapped(index).cast(), text.len());
+        }
+        Ok(())
+    }
+
+    /// Copy the intermediate present image of window `target` to its overlay image, upscaling
+    /// it to the window resolution.
+    unsafe fn upscale_to_overlay_image(
+        &self,
+        target: usize,
+        command_buffer: vk::CommandBuffer,
+        present_index: usize,
+    ) {
+        let window = &self.targets[target];
+        let (Some(intermediate_image), Some(overlay_image)) =
+            (&window.intermediate_present_image, &window.overlay_image)
+        else {
+            return;
+        };
+        let intermediate_image = &intermediate_image[present_index];
+        let overlay_image = &overlay_image[0];
+
+        // Make shader writes visible to the blit, the overlay image was last read by a blit.
+        let memory_barriers = [
+            vk::ImageMemoryBarrier::builder()
+                .image(**intermediate_image.image)
+                .subresource_range(self.image_subresource_range)
+                .old_layout(vk::ImageLayout::GENERAL)
+                .new_layout(vk::ImageLayout::GENERAL)
+                .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+                .dst_access_mask(vk::AccessFlags::TRANSFER_READ)
+                .build(),
+            vk::ImageMemoryBarrier::builder()
+                .image(**overlay_image.image)
+                .subresource_range(self.image_subresource_range)
+                .old_layout(vk::ImageLayout::GENERAL)
+                .new_layout(vk::ImageLayout::GENERAL)
+                .src_access_mask(vk::AccessFlags::TRANSFER_READ)
+                .dst_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+                .build(),
+        ];
+        self.device.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::COMPUTE_SHADER | vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[],
+            &memory_barriers,
+        );
+
+        let subresource = vk::ImageSubresourceLayers {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            mip_level: 0,
+            base_array_layer: 0,
+            layer_count: 1,
+        };
+        let offsets = |extent: vk::Extent2D| {
+            [
+                vk::Offset3D::default(),
+                vk::Offset3D {
+                    x: i32::try_from(extent.width).unwrap(),
+                    y: i32::try_from(extent.height).unwrap(),
+                    z: 1,
+                },
+            ]
+        };
+        let region = vk::ImageBlit {
+            src_subresource: subresource,
+            src_offsets: offsets(window.surface_info.render_resolution),
+            dst_subresource: subresource,
+            dst_offsets: offsets(window.surface_info.surface_resolution),
+        };
+        self.device.cmd_blit_image(
+            command_buffer,
+            **intermediate_image.image,
+            vk::ImageLayout::GENERAL,
+            **overlay_image.image,
+            vk::ImageLayout::GENERAL,
+            &[region],
+            window.upscale_filter.vk_filter(),
+        );
+
+        let memory_barrier = vk::ImageMemoryBarrier::builder()
+            .image(**overlay_image.image)
+            .subresource_range(self.image_subresource_range)
+            .old_layout(vk::ImageLayout::GENERAL)
+            .new_layout(vk::ImageLayout::GENERAL)
+            .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+            .dst_access_mask(vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE)
+            .build();
+        self.device.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[],
+            &[memory_barrier],
+        );
+    }
+
+    /// Draw the overlay on top of the upscaled output of the shaders of window `target`.
+    pub(super) unsafe fn render_overlay(
+        &mut self,
+        target: usize,
+        command_buffer: vk::CommandBuffer,
+        present_index: usize,
+    ) -> VResult<()> {
+        let window = &mut self.targets[target];
+        let Some(resources) = &mut window.overlay else {
+            return Ok(());
+        };
+        let (write_descriptor_set, descriptor_sets) = resources.get_descriptor_sets(
+            &self.device,
+            &self.available_images,
+            &self.available_buffers,
+            &window.present_name,
+            present_index,
+            self.num_frames,
+        )?;
+        let window = &self.targets[target];
+        let Some(resources) = &window.overlay else {
+            return Ok(());
+        };
+
+        // Draw over the output of the previous passes.
+        self.upscale_to_overlay_image(target, command_buffer, present_index);
+        self.shader_write_barrier(command_buffer);
+
+        self.bind_pipeline(command_buffer, &resources.pipeline);
+        self.push_constants(
+            command_buffer,
+            &resources.pipeline_layout,
+            &resources.shader_module,
+            &push_constants(&window.surface_info),
+        );
+        self.push_descriptors(
+            command_buffer,
+            &resources.pipeline_layout,
+            resources.descriptors.push_set(),
+            &write_descriptor_set,
+        );
+        self.bind_descriptor_sets(command_buffer, &resources.pipeline_layout, &descriptor_sets);
+        self.dispatch(
+            command_buffer,
+            &resources.shader_module,
+            window.surface_info.surface_resolution,
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,108 @@
+use std::rc::Rc;
+
+use ash::vk;
+
+use crate::error::VResult;
+
+use super::resources::{device::Device, query_pool::QueryPool};
+
+/// One query per shader pass, or a pair of timestamps, recorded into every frame. Remembers
+/// which passes the submitted frame recorded until their results are read back.
+pub(super) struct PassQueries<T> {
+    query_pool: Rc<QueryPool>,
+    query_type: vk::QueryType,
+    /// Passes recorded into the last submitted frame, in query order.
+    pending: Vec<T>,
+}
+
+impl<T> PassQueries<T> {
+    pub(super) unsafe fn new(
+        device: &Rc<Device>,
+        query_type: vk::QueryType,
+        pipeline_statistics: vk::QueryPipelineStatisticFlags,
+        num_passes: usize,
+    ) -> VResult<Self> {
+        let query_count = u32::try_from(num_passes).unwrap() * Self::queries_per_pass(query_type);
+        let query_pool = QueryPool::new(device, query_type, pipeline_statistics, query_count)?;
+        Ok(Self {
+            query_pool,
+            query_type,
+            pending: Vec::new(),
+        })
+    }
+
+    /// Timestamps are written at the start and end of a pass.
+    fn queries_per_pass(query_type: vk::QueryType) -> u32 {
+        if query_type == vk::QueryType::TIMESTAMP {
+            2
+        } else {
+            1
+        }
+    }
+
+    fn per_pass(&self) -> u32 {
+        Self::queries_per_pass(self.query_type)
+    }
+
+    /// The passes of the last submitted frame with their results, `None` if nothing was
+    /// recorded or the results are not available yet. Clears the passes either way.
+    pub(super) unsafe fn take_results(&mut self) -> VResult<Option<(Vec<T>, Vec<u64>)>> {
+        let pending = std::mem::take(&mut self.pending);
+        if pending.is_empty() {
+            return Ok(None);
+        }
+        let query_count = u32::try_from(pending.len()).unwrap() * self.per_pass();
+        let results = self.query_pool.results(0, query_count)?;
+        Ok(results.map(|results| (pending, results)))
+    }
+
+    pub(super) unsafe fn reset(&self, device: &Device, command_buffer: vk::CommandBuffer) {
+        let query_pool = &self.query_pool;
+        device.cmd_reset_query_pool(command_buffer, ***query_pool, 0, query_pool.query_count);
+    }
+
+    pub(super) unsafe fn begin(
+        &self,
+        device: &Device,
+        command_buffer: vk::CommandBuffer,
+        index: usize,
+    ) {
+        let query = u32::try_from(index).unwrap() * self.per_pass();
+        if self.query_type == vk::QueryType::TIMESTAMP {
+            device.cmd_write_timestamp(
+                command_buffer,
+                vk::PipelineStageFlags::TOP_OF_PIPE,
+                **self.query_pool,
+                query,
+            );
+        } else {
+            device.cmd_begin_query(
+                command_buffer,
+                **self.query_pool,
+                query,
+                vk::QueryControlFlags::empty(),
+            );
+        }
+    }
+
+    pub(super) unsafe fn end(
+        &mut self,
+        device: &Device,
+        command_buffer: vk::CommandBuffer,
+        index: usize,
+        pass: T,
+    ) {
+        let query = u32::try_from(index).unwrap() * self.per_pass();
+        if self.query_type == vk::QueryType::TIMESTAMP {
+            device.cmd_write_timestamp(
+                command_buffer,
+                vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                **self.query_pool,
+                query + 1,
+            );
+        } else {
+            device.cmd_end_query(command_buffer, **self.query_pool, query);
+        }
+        self.pending.push(pass);
+    }
+}
@@ -0,0 +1,154 @@
+use std::rc::Rc;
+
+use ash::vk;
+use log::{info, warn};
+
+use crate::error::VResult;
+
+use super::{pass_queries::PassQueries, resources::device::Device};
+
+/// Compute shader invocations of a single pass in the last collected frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PassStatistics {
+    pub invocations: u64,
+    /// One invocation per pixel of the render resolution.
+    pub expected_invocations: u64,
+}
+
+impl PassStatistics {
+    /// Fewer invocations than pixels means the dispatch was truncated and edge pixels are
+    /// skipped.
+    #[must_use]
+    pub fn skips_pixels(&self) -> bool {
+        self.invocations < self.expected_invocations
+    }
+}
+
+/// Counts compute shader invocations of each shader pass with pipeline statistics queries.
+pub struct PipelineStatistics {
+    /// An invocation count per pass, with its name and expected invocations.
+    queries: Option<PassQueries<(String, u64)>>,
+    passes: Vec<(String, PassStatistics)>,
+    /// Passes which were already reported to skip pixels.
+    reported: Vec<String>,
+}
+
+impl PipelineStatistics {
+    pub(super) unsafe fn new(device: &Rc<Device>, num_passes: usize) -> VResult<Self> {
+        let queries = if device.pipeline_statistics && num_passes > 0 {
+            Some(PassQueries::new(
+                device,
+                vk::QueryType::PIPELINE_STATISTICS,
+                vk::QueryPipelineStatisticFlags::COMPUTE_SHADER_INVOCATIONS,
+                num_passes,
+            )?)
+        } else {
+            None
+        };
+        Ok(Self {
+            queries,
+            passes: Vec::new(),
+            reported: Vec::new(),
+        })
+    }
+
+    /// Replace the pass statistics with the invocation counts of the last submitted frame once
+    /// its fence signaled, and warn about passes skipping pixels.
+    pub(super) unsafe fn collect(&mut self) -> VResult<()> {
+        let Some(queries) = &mut self.queries else {
+            return Ok(());
+        };
+        let Some((passes, invocations)) = queries.take_results()? else {
+            return Ok(());
+        };
+        self.passes = passes
+            .into_iter()
+            .zip(invocations)
+            .map(|((name, expected_invocations), invocations)| {
+                let statistics = PassStatistics {
+                    invocations,
+                    expected_invocations,
+                };
+                (name, statistics)
+            })
+            .collect();
+
+        // Warn once per pass until the render resolution changes, see `clear_reports`.
+        for (name, statistics) in &self.passes {
+            if statistics.skips_pixels() && !self.reported.contains(name) {
+                warn!(
+                    "{name} ran {} invocations for {} pixels, edge pixels are skipped",
+                    statistics.invocations, statistics.expected_invocations
+                );
+                self.reported.push(name.clone());
+            }
+        }
+        Ok(())
+    }
+
+    /// Forget which passes were reported to skip pixels, e.g. when the render resolution
+    /// changed.
+    pub(super) fn clear_reports(&mut self) {
+        self.reported.clear();
+    }
+
+    /// Zero the invocation counters, recorded at the start of the frame.
+    pub(super) unsafe fn reset(&self, device: &Device, command_buffer: vk::CommandBuffer) {
+        if let Some(queries) = &self.queries {
+            queries.reset(device, command_buffer);
+        }
+    }
+
+    /// Start counting the invocations of pass `index`, its dispatch must follow.
+    pub(super) unsafe fn begin_pass(
+        &self,
+        device: &Device,
+        command_buffer: vk::CommandBuffer,
+        index: usize,
+    ) {
+        if let Some(queries) = &self.queries {
+            queries.begin(device, command_buffer, index);
+        }
+    }
+
+    /// Stop counting pass `index`, whose dispatch covers `expected_invocations` pixels.
+    pub(super) unsafe fn end_pass(
+        &mut self,
+        device: &Device,
+        command_buffer: vk::CommandBuffer,
+        index: usize,
+        name: String,
+        expected_invocations: u64,
+    ) {
+        if let Some(queries) = &mut self.queries {
+            queries.end(device, command_buffer, index, (name, expected_invocations));
+        }
+    }
+
+    #[must_use]
+    pub fn is_enabled(&self) -> bool {
+        self.queries.is_some()
+    }
+
+    /// Statistics of each pass of the last collected frame, keyed by shader path.
+    #[must_use]
+    pub fn passes(&self) -> &[(String, PassStatistics)] {
+        &self.passes
+    }
+
+    pub fn print(&self) {
+        info!("Pipeline statistics");
+        for (name, statistics) in &self.passes {
+            let PassStatistics {
+                invocations,
+                expected_invocations,
+            } = statistics;
+            let note = if statistics.skips_pixels() {
+                " (skips pixels)"
+            } else {
+                ""
+            };
+            info!("  {name: <20} {invocations:>12} invocations / {expected_invocations:>12} pixels{note}");
+        }
+    }
+}
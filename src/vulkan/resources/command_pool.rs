@@ -6,7 +6,7 @@ use ash::{self, vk};
 
 use crate::error::VResult;
 
-use super::{device::Device, physical_device::PhysicalDevice};
+use super::device::Device;
 
 pub struct CommandPool {
     device: Rc<Device>,
@@ -22,13 +22,13 @@ impl Deref for CommandPool {
 }
 
 impl CommandPool {
-    pub unsafe fn new(physical_device: &PhysicalDevice, device: &Rc<Device>) -> VResult<Rc<Self>> {
+    pub unsafe fn new(device: &Rc<Device>, queue_family_index: u32) -> VResult<Rc<Self>> {
         debug!("Creating command pool");
         let device = device.clone();
 
         let pool_create_info = vk::CommandPoolCreateInfo::builder()
             .flags(vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER)
-            .queue_family_index(physical_device.compute_queue_family_index);
+            .queue_family_index(queue_family_index);
 
         let command_pool = device.create_command_pool(&pool_create_info, None)?;
 
@@ -6,7 +6,7 @@ use ash::vk;
 
 use crate::{
     error::{Error, VResult},
-    vulkan::resources::descriptors::DescriptorBinding,
+    vulkan::resources::shader_module::analysis::ArraySize,
 };
 
 use super::{descriptors::Descriptors, device::Device};
@@ -25,31 +25,86 @@ impl Deref for DescriptorLayout {
 }
 
 impl DescriptorLayout {
-    pub unsafe fn new(device: &Rc<Device>, descriptors: &Descriptors) -> VResult<Rc<Self>> {
-        debug!("Creating descriptor layouts");
+    /// Create the layout of the set `set`. Push descriptor layouts are updated using
+    /// `cmd_push_descriptor_set` instead of being allocated from a pool.
+    pub unsafe fn new(
+        device: &Rc<Device>,
+        descriptors: &Descriptors,
+        set: usize,
+        push_descriptor: bool,
+    ) -> VResult<Rc<Self>> {
+        debug!("Creating descriptor layout for set {set}");
+
+        let descriptors = descriptors
+            .iter()
+            .filter(|descriptor| descriptor.set() == set)
+            .collect::<Vec<_>>();
 
         let mut used_bindings = HashMap::new();
-        for descriptor in descriptors.iter() {
+        for descriptor in &descriptors {
             let binding = descriptor.binding();
             let name = &descriptor.name;
             if let Some(prev) = used_bindings.get(&binding) {
                 let msg = format!(
-                    "Binding {} is shared by {} and {}. All bindings must be unique",
-                    binding, descriptor.name, prev
+                    "Binding {} of set {} is shared by {} and {}. All bindings must be unique",
+                    binding, set, descriptor.name, prev
                 );
                 return Err(Error::Local(msg));
             }
             used_bindings.insert(binding, name);
+
+            if descriptor.array_size() == Some(ArraySize::Runtime) && !device.descriptor_indexing {
+                let msg = format!(
+                    "Runtime-sized array {name} requires descriptor indexing, which is not supported"
+                );
+                return Err(Error::Local(msg));
+            }
         }
 
         let device = device.clone();
         let bindings = descriptors
             .iter()
-            .map(DescriptorBinding::as_descriptor_set_layout_binding)
+            .map(|descriptor| descriptor.as_descriptor_set_layout_binding())
             .collect::<Vec<_>>();
-        let descriptor_layout_create_info = vk::DescriptorSetLayoutCreateInfo::builder()
-            .flags(vk::DescriptorSetLayoutCreateFlags::PUSH_DESCRIPTOR_KHR)
+
+        // Arrays do not need to be filled completely. Variable-sized arrays are sized on
+        // allocation, which requires an update-after-bind pool.
+        let binding_flags = descriptors
+            .iter()
+            .map(|descriptor| {
+                if descriptor.variable_count() {
+                    vk::DescriptorBindingFlags::PARTIALLY_BOUND
+                        | vk::DescriptorBindingFlags::VARIABLE_DESCRIPTOR_COUNT
+                        | vk::DescriptorBindingFlags::UPDATE_AFTER_BIND
+                } else if device.descriptor_indexing && descriptor.array_size().is_some() {
+                    vk::DescriptorBindingFlags::PARTIALLY_BOUND
+                } else {
+                    vk::DescriptorBindingFlags::empty()
+                }
+            })
+            .collect::<Vec<_>>();
+        let mut binding_flags_create_info =
+            vk::DescriptorSetLayoutBindingFlagsCreateInfo::builder().binding_flags(&binding_flags);
+
+        let flags = if push_descriptor {
+            vk::DescriptorSetLayoutCreateFlags::PUSH_DESCRIPTOR_KHR
+        } else if descriptors
+            .iter()
+            .any(|descriptor| descriptor.variable_count())
+        {
+            vk::DescriptorSetLayoutCreateFlags::UPDATE_AFTER_BIND_POOL
+        } else {
+            vk::DescriptorSetLayoutCreateFlags::empty()
+        };
+
+        // The flags struct is only valid with descriptor indexing.
+        let mut descriptor_layout_create_info = vk::DescriptorSetLayoutCreateInfo::builder()
+            .flags(flags)
             .bindings(&bindings);
+        if device.descriptor_indexing && binding_flags.iter().any(|flags| !flags.is_empty()) {
+            descriptor_layout_create_info =
+                descriptor_layout_create_info.push_next(&mut binding_flags_create_info);
+        }
         let layout = device.create_descriptor_set_layout(&descriptor_layout_create_info, None)?;
 
         Ok(Rc::new(DescriptorLayout { device, layout }))
@@ -0,0 +1,80 @@
+use std::{ops::Deref, rc::Rc};
+
+use log::debug;
+
+use ash::vk;
+
+use crate::error::VResult;
+
+use super::device::Device;
+
+/// Pool for classic (non-push) descriptor sets. Allocated sets are freed with the pool.
+pub struct DescriptorPool {
+    device: Rc<Device>,
+    descriptor_pool: vk::DescriptorPool,
+}
+
+impl Deref for DescriptorPool {
+    type Target = vk::DescriptorPool;
+
+    fn deref(&self) -> &Self::Target {
+        &self.descriptor_pool
+    }
+}
+
+impl DescriptorPool {
+    pub unsafe fn new(
+        device: &Rc<Device>,
+        pool_sizes: &[vk::DescriptorPoolSize],
+        max_sets: usize,
+        update_after_bind: bool,
+    ) -> VResult<Rc<Self>> {
+        debug!("Creating descriptor pool");
+        let device = device.clone();
+
+        // Required for layouts with variable descriptor counts.
+        let flags = if update_after_bind {
+            vk::DescriptorPoolCreateFlags::UPDATE_AFTER_BIND
+        } else {
+            vk::DescriptorPoolCreateFlags::empty()
+        };
+        let pool_create_info = vk::DescriptorPoolCreateInfo::builder()
+            .flags(flags)
+            .pool_sizes(pool_sizes)
+            .max_sets(u32::try_from(max_sets).unwrap());
+        let descriptor_pool = device.create_descriptor_pool(&pool_create_info, None)?;
+
+        Ok(Rc::new(Self {
+            device,
+            descriptor_pool,
+        }))
+    }
+
+    /// Allocate a set per layout. `variable_counts` holds the size of the variable-sized array of
+    /// each set, and is ignored for layouts without one.
+    pub unsafe fn allocate(
+        &self,
+        layouts: &[vk::DescriptorSetLayout],
+        variable_counts: &[u32],
+    ) -> VResult<Vec<vk::DescriptorSet>> {
+        let mut variable_count_info =
+            vk::DescriptorSetVariableDescriptorCountAllocateInfo::builder()
+                .descriptor_counts(variable_counts);
+        let mut allocate_info = vk::DescriptorSetAllocateInfo::builder()
+            .descriptor_pool(self.descriptor_pool)
+            .set_layouts(layouts);
+        if self.device.variable_descriptor_count {
+            allocate_info = allocate_info.push_next(&mut variable_count_info);
+        }
+        Ok(self.device.allocate_descriptor_sets(&allocate_info)?)
+    }
+}
+
+impl Drop for DescriptorPool {
+    fn drop(&mut self) {
+        debug!("Destroying descriptor pool");
+        unsafe {
+            self.device.destroy_descriptor_pool(**self, None);
+        }
+    }
+}
@@ -1,4 +1,8 @@
-use std::ops::{Deref, DerefMut};
+use std::{
+    collections::HashMap,
+    ops::{Deref, DerefMut},
+    rc::Rc,
+};
 
 use log::debug;
 
@@ -6,12 +10,20 @@ use ash::vk;
 
 use crate::{
     error::{Error, VResult},
+    utils::lcm,
     vulkan::{
-        resources::shader_module::analysis::DescriptorInfo, AvailableBuffers, AvailableImages,
+        resources::shader_module::analysis::{ArraySize, DescriptorInfo},
+        AvailableBuffers, AvailableImages,
     },
 };
 
-use super::shader_module::ShaderModule;
+use super::{descriptor_pool::DescriptorPool, device::Device, shader_module::ShaderModule};
+
+/// Number of descriptors reserved for runtime-sized arrays (`uniform sampler2D textures[]`)
+/// which can't have a variable descriptor count: in push descriptor sets, which are limited by
+/// `maxPushDescriptors` (only guaranteed to be 32), if the array is not the last binding of its
+/// set or without `descriptorBindingVariableDescriptorCount`.
+pub const RUNTIME_ARRAY_CAPACITY: usize = 16;
 
 fn write_descriptor_set_builder_stub(
     descriptor_binding: usize,
@@ -28,41 +40,96 @@ pub struct DescriptorBinding {
     /// Name of the object.
     pub name: String,
 
+    /// Descriptor set index of the object (specified in the shader, defaults to 0).
+    set: usize,
+
     /// Binding index of the object (specified in the shader).
     binding: usize,
 
     /// The type of the underlying buffer/image.
     storage_type: vk::DescriptorType,
 
+    /// Array size of the binding, `None` for a single descriptor.
+    array_size: Option<ArraySize>,
+
+    /// Descriptors reserved in the layout for a runtime-sized array.
+    runtime_capacity: usize,
+
+    /// Whether the runtime-sized array is allocated with the number of bound descriptors.
+    variable_count: bool,
+
     /// Instances, actual data, to be bound. Created and linked in application code.
     pub instances: Vec<vk::WriteDescriptorSet>,
+
+    /// Instance bound in every frame, instead of selecting it by frame index.
+    pinned_instance: Option<usize>,
 }
 
 impl DescriptorBinding {
+    #[must_use]
+    pub fn set(&self) -> usize {
+        self.set
+    }
+
     #[must_use]
     pub fn binding(&self) -> usize {
         self.binding
     }
 
+    #[must_use]
+    pub fn array_size(&self) -> Option<ArraySize> {
+        self.array_size
+    }
+
+    /// Whether the binding is a runtime-sized array with a variable descriptor count, which
+    /// requires an update-after-bind layout and pool.
+    #[must_use]
+    pub fn variable_count(&self) -> bool {
+        self.variable_count
+    }
+
+    /// Number of descriptors in the layout of the binding. For runtime-sized arrays this is the
+    /// most the array can hold.
+    #[must_use]
+    pub fn descriptor_count(&self) -> usize {
+        match self.array_size {
+            None => 1,
+            Some(ArraySize::Fixed(size)) => size,
+            Some(ArraySize::Runtime) => self.runtime_capacity,
+        }
+    }
+
+    /// Number of descriptors allocated for the binding, the largest bound array for variable
+    /// descriptor counts. Requires the binding to be associated.
+    fn allocated_count(&self) -> usize {
+        if self.variable_count {
+            self.instances
+                .iter()
+                .map(|instance| usize::try_from(instance.descriptor_count).unwrap())
+                .max()
+                .unwrap_or(0)
+                .max(1)
+        } else {
+            self.descriptor_count()
+        }
+    }
+
     #[must_use]
     pub fn as_descriptor_set_layout_binding(&self) -> vk::DescriptorSetLayoutBinding {
         vk::DescriptorSetLayoutBinding {
             binding: u32::try_from(self.binding).unwrap(),
             descriptor_type: self.storage_type,
-            descriptor_count: 1,
+            descriptor_count: u32::try_from(self.descriptor_count()).unwrap(),
             stage_flags: vk::ShaderStageFlags::COMPUTE,
             ..Default::default()
         }
     }
 
-    fn get_write_descriptor_set_entry(
+    fn associate(
         &mut self,
         available_images: &AvailableImages,
         available_buffers: &AvailableBuffers,
-        present_name: &str,
-        present_index: usize,
-        frame_index: usize,
-    ) -> VResult<vk::WriteDescriptorSet> {
+    ) -> VResult<()> {
         if self.instances.is_empty() {
             debug!(
                 "Associating buffers for binding {}: {}",
@@ -74,12 +141,12 @@ impl DescriptorBinding {
                 available_images.get(&self.name).map(|images| {
                     images
                         .iter()
-                        .map(|(_, _, image_info)| {
+                        .map(|(_, image_infos)| {
                             write_descriptor_set_builder_stub(self.binding, self.storage_type)
-                                .image_info(image_info.as_ref())
+                                .image_info(image_infos.as_ref())
                                 .build()
                         })
-                        .collect()
+                        .collect::<Vec<_>>()
                 })
             };
 
@@ -96,45 +163,106 @@ impl DescriptorBinding {
                 })
             };
 
-            self.instances = search_in_images()
+            let instances = search_in_images()
                 .or_else(search_in_buffers)
                 .ok_or_else(|| {
                     let msg = format!("No buffer for binding {}: {}", self.binding, self.name);
                     Error::Local(msg)
                 })?;
+
+            // Arrays may be partially filled, but never overfilled.
+            let descriptor_count = self.descriptor_count();
+            for instance in &instances {
+                let count = usize::try_from(instance.descriptor_count).unwrap();
+                if count > descriptor_count {
+                    let msg = format!(
+                        "Binding {}: {} holds {descriptor_count} descriptors, got {count}",
+                        self.binding, self.name
+                    );
+                    return Err(Error::Local(msg));
+                }
+                if count < descriptor_count
+                    && self.array_size == Some(ArraySize::Fixed(descriptor_count))
+                {
+                    debug!(
+                        "Binding {}: {} is partially bound ({count}/{descriptor_count})",
+                        self.binding, self.name
+                    );
+                }
+            }
+
+            self.instances = instances;
         }
 
+        Ok(())
+    }
+
+    /// Requires the binding to be associated.
+    fn get_write_descriptor_set_entry(
+        &self,
+        present_name: &str,
+        present_index: usize,
+        frame_index: usize,
+    ) -> vk::WriteDescriptorSet {
         let instance_index = if self.name == present_name {
             present_index
+        } else if let Some(pinned_instance) = self.pinned_instance {
+            pinned_instance % self.instances.len()
         } else {
             frame_index % self.instances.len()
         };
-        Ok(self.instances[instance_index])
+        self.instances[instance_index]
+    }
+}
+
+/// Classic descriptor sets, allocated from a pool and written once on association. There is one
+/// set per combination of bound instances: `num_frame_variants` for the multi-buffered resources,
+/// times the number of present images if the set contains the present image.
+pub struct AllocatedDescriptorSets {
+    _descriptor_pool: Rc<DescriptorPool>,
+    num_frame_variants: usize,
+    sets: Vec<vk::DescriptorSet>,
+}
+
+impl AllocatedDescriptorSets {
+    #[must_use]
+    pub fn get(&self, present_index: usize, frame_index: usize) -> vk::DescriptorSet {
+        let frame_variant = frame_index % self.num_frame_variants;
+        let variant = present_index * self.num_frame_variants + frame_variant;
+        self.sets[variant % self.sets.len()]
     }
 }
 
 /// Descriptor sets have multiple instances which can be bound. This is per-shader data, binding
 /// indices do not need to be consistent across shaders. Currently the final mapping is done via
 /// buffer/image name.
-pub struct Descriptors(Vec<DescriptorBinding>);
+///
+/// The set containing the present image changes every frame and is pushed (see
+/// `VK_KHR_push_descriptor`), all other sets are allocated from a pool and only rewritten when
+/// the bound resources change. Without push descriptor support, all sets are allocated.
+pub struct Descriptors {
+    bindings: Vec<DescriptorBinding>,
+    num_sets: usize,
+    push_set: Option<usize>,
+}
 
 impl Deref for Descriptors {
     type Target = [DescriptorBinding];
 
     fn deref(&self) -> &Self::Target {
-        &self.0
+        &self.bindings
     }
 }
 
 impl DerefMut for Descriptors {
     fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.0
+        &mut self.bindings
     }
 }
 
 impl Descriptors {
     #[must_use]
-    pub fn new(shader_module: &ShaderModule) -> Self {
+    pub fn new(shader_module: &ShaderModule, present_name: &str, device: &Device) -> Self {
         debug!("Creating descriptor bindings");
 
         // TODO immutable samplers, what are immutable samplers???
@@ -145,9 +273,14 @@ impl Descriptors {
             .filter(|declaration| declaration.binding.is_some())
             .map(|declaration| DescriptorBinding {
                 name: declaration.name.clone(),
+                set: declaration.set_index(),
                 binding: declaration.binding.unwrap(),
                 storage_type: declaration.storage(),
+                array_size: declaration.array_size,
+                runtime_capacity: RUNTIME_ARRAY_CAPACITY,
+                variable_count: false,
                 instances: Vec::new(),
+                pinned_instance: None,
             });
 
         let blocks = shader_module
@@ -156,32 +289,254 @@ impl Descriptors {
             .filter(|declaration| declaration.binding.is_some())
             .map(|declaration| DescriptorBinding {
                 name: declaration.name().to_string(),
+                set: declaration.set_index(),
                 binding: declaration.binding.unwrap(),
                 storage_type: declaration.storage,
+                array_size: None,
+                runtime_capacity: RUNTIME_ARRAY_CAPACITY,
+                variable_count: false,
                 instances: Vec::new(),
+                pinned_instance: None,
             });
 
-        Descriptors(vars.chain(blocks).collect())
+        let mut bindings = vars.chain(blocks).collect::<Vec<_>>();
+
+        // Sets must be contiguous, unused sets get an empty layout. Keep at least one set.
+        let num_sets = bindings
+            .iter()
+            .map(|binding| binding.set + 1)
+            .max()
+            .unwrap_or(1);
+        let push_set = device.push_descriptor.then(|| {
+            bindings
+                .iter()
+                .find(|binding| binding.name == present_name)
+                .map_or(0, DescriptorBinding::set)
+        });
+
+        // Only the last binding of an allocated set can have a variable descriptor count, and
+        // update-after-bind is only enabled for images.
+        if device.variable_descriptor_count {
+            let is_image = |binding: &DescriptorBinding| {
+                matches!(
+                    binding.storage_type,
+                    vk::DescriptorType::COMBINED_IMAGE_SAMPLER | vk::DescriptorType::STORAGE_IMAGE
+                )
+            };
+            let last_bindings = bindings
+                .iter()
+                .map(|binding| {
+                    bindings
+                        .iter()
+                        .filter(|other| other.set == binding.set)
+                        .all(|other| other.binding <= binding.binding)
+                })
+                .collect::<Vec<_>>();
+            for (binding, last) in bindings.iter_mut().zip(last_bindings) {
+                binding.variable_count = last
+                    && is_image(binding)
+                    && binding.array_size == Some(ArraySize::Runtime)
+                    && Some(binding.set) != push_set;
+            }
+
+            // Share the limits between the variable arrays, minus the other descriptors of the
+            // shader. Combined image samplers count as both images and samplers.
+            let other_count = |filter: &dyn Fn(&DescriptorBinding) -> bool| {
+                bindings
+                    .iter()
+                    .filter(|binding| !binding.variable_count && filter(binding))
+                    .map(DescriptorBinding::descriptor_count)
+                    .sum::<usize>()
+            };
+            let is_sampler = |binding: &DescriptorBinding| {
+                matches!(
+                    binding.storage_type,
+                    vk::DescriptorType::COMBINED_IMAGE_SAMPLER | vk::DescriptorType::SAMPLER
+                )
+            };
+            let limits = device.update_after_bind_limits;
+            let images = limits.images.saturating_sub(other_count(&|binding| {
+                is_image(binding) || binding.storage_type == vk::DescriptorType::SAMPLED_IMAGE
+            }));
+            // The sampler limits only apply to arrays of combined image samplers.
+            let samplers = if bindings
+                .iter()
+                .any(|binding| binding.variable_count && is_sampler(binding))
+            {
+                limits.samplers.saturating_sub(other_count(&is_sampler))
+            } else {
+                usize::MAX
+            };
+            let resources = limits.resources.saturating_sub(other_count(&|_| true));
+            let available = images.min(samplers).min(resources);
+            let num_variable = bindings
+                .iter()
+                .filter(|binding| binding.variable_count)
+                .count();
+            if let Some(capacity) = available.checked_div(num_variable) {
+                for binding in bindings.iter_mut().filter(|binding| binding.variable_count) {
+                    binding.runtime_capacity = capacity;
+                }
+            }
+        }
+
+        Descriptors {
+            bindings,
+            num_sets,
+            push_set,
+        }
+    }
+
+    #[must_use]
+    pub fn num_sets(&self) -> usize {
+        self.num_sets
+    }
+
+    /// The set index which is bound using push descriptors, if any.
+    #[must_use]
+    pub fn push_set(&self) -> Option<usize> {
+        self.push_set
+    }
+
+    /// Whether any binding of the set is not associated with a buffer or image. Happens initially
+    /// and after invalidating the association cache.
+    #[must_use]
+    pub fn needs_association(&self, set: usize) -> bool {
+        self.iter()
+            .any(|binding| binding.set == set && binding.instances.is_empty())
     }
 
+    fn associate(
+        &mut self,
+        set: usize,
+        available_images: &AvailableImages,
+        available_buffers: &AvailableBuffers,
+    ) -> VResult<()> {
+        self.iter_mut()
+            .filter(|binding| binding.set == set)
+            .try_for_each(|binding| binding.associate(available_images, available_buffers))
+    }
+
+    /// Pin bindings to the instances in `pinned_instances` by name, all others are selected by
+    /// frame index. Returns the sets whose bindings changed.
+    pub fn pin_instances(&mut self, pinned_instances: &HashMap<String, usize>) -> Vec<usize> {
+        let mut changed_sets = Vec::new();
+        for binding in self.iter_mut() {
+            let pinned_instance = pinned_instances.get(&binding.name).copied();
+            if binding.pinned_instance != pinned_instance {
+                binding.pinned_instance = pinned_instance;
+                changed_sets.push(binding.set);
+            }
+        }
+        changed_sets
+    }
+
+    fn get_set_entries(
+        &self,
+        set: usize,
+        present_name: &str,
+        present_index: usize,
+        frame_index: usize,
+    ) -> Vec<vk::WriteDescriptorSet> {
+        self.iter()
+            .filter(|binding| binding.set == set)
+            .map(|binding| {
+                binding.get_write_descriptor_set_entry(present_name, present_index, frame_index)
+            })
+            // Empty arrays (e.g. a bindless table without entries) are left unbound.
+            .filter(|entry| entry.descriptor_count > 0)
+            .collect()
+    }
+
+    /// Collect, and, if missing, associate the available buffers of the set `set`.
     pub fn get_write_descriptor_set(
         &mut self,
+        set: usize,
         available_images: &AvailableImages,
         available_buffers: &AvailableBuffers,
         present_name: &str,
         present_index: usize,
         frame_index: usize,
     ) -> VResult<Vec<vk::WriteDescriptorSet>> {
-        self.iter_mut()
-            .map(|descriptor| {
-                descriptor.get_write_descriptor_set_entry(
-                    available_images,
-                    available_buffers,
-                    present_name,
-                    present_index,
-                    frame_index,
-                )
+        self.associate(set, available_images, available_buffers)?;
+        Ok(self.get_set_entries(set, present_name, present_index, frame_index))
+    }
+
+    /// Associate the available buffers of the set `set` and write them into newly allocated
+    /// descriptor sets, one for each combination of bound instances.
+    pub unsafe fn allocate_descriptor_sets(
+        &mut self,
+        device: &Rc<Device>,
+        set: usize,
+        layout: vk::DescriptorSetLayout,
+        available_images: &AvailableImages,
+        available_buffers: &AvailableBuffers,
+        present_name: &str,
+    ) -> VResult<AllocatedDescriptorSets> {
+        self.associate(set, available_images, available_buffers)?;
+
+        let bindings = self
+            .iter()
+            .filter(|binding| binding.set == set)
+            .collect::<Vec<_>>();
+        let num_frame_variants = bindings
+            .iter()
+            .filter(|binding| binding.name != present_name)
+            .map(|binding| binding.instances.len())
+            .fold(1, lcm);
+        let num_present_variants = bindings
+            .iter()
+            .find(|binding| binding.name == present_name)
+            .map_or(1, |binding| binding.instances.len());
+        let num_sets = num_frame_variants * num_present_variants;
+
+        let pool_sizes = bindings
+            .iter()
+            .map(|binding| vk::DescriptorPoolSize {
+                ty: binding.storage_type,
+                descriptor_count: u32::try_from(binding.allocated_count() * num_sets).unwrap(),
             })
-            .collect()
+            .collect::<Vec<_>>();
+        // A pool without sizes is invalid, but sets may be empty.
+        let pool_sizes = if pool_sizes.is_empty() {
+            vec![vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::STORAGE_BUFFER,
+                descriptor_count: 1,
+            }]
+        } else {
+            pool_sizes
+        };
+
+        // The variable-sized array, if any, is the last binding of the set.
+        let variable_count = bindings
+            .iter()
+            .find(|binding| binding.variable_count)
+            .map(|binding| u32::try_from(binding.allocated_count()).unwrap());
+        let descriptor_pool =
+            DescriptorPool::new(device, &pool_sizes, num_sets, variable_count.is_some())?;
+        let sets = descriptor_pool.allocate(
+            &vec![layout; num_sets],
+            &vec![variable_count.unwrap_or(0); num_sets],
+        )?;
+
+        for (variant, descriptor_set) in sets.iter().enumerate() {
+            let present_index = variant / num_frame_variants;
+            let frame_index = variant % num_frame_variants;
+            let entries = self
+                .get_set_entries(set, present_name, present_index, frame_index)
+                .into_iter()
+                .map(|mut entry| {
+                    entry.dst_set = *descriptor_set;
+                    entry
+                })
+                .collect::<Vec<_>>();
+            device.update_descriptor_sets(&entries, &[]);
+        }
+
+        Ok(AllocatedDescriptorSets {
+            _descriptor_pool: descriptor_pool,
+            num_frame_variants,
+            sets,
+        })
     }
 }
@@ -1,15 +1,50 @@
 use std::{ffi::CStr, ops::Deref, rc::Rc};
 
-use log::debug;
+use log::{debug, warn};
 
 use ash::{self, extensions, vk};
 
-use crate::error::VResult;
+use crate::{
+    error::{Error, VResult},
+    vulkan::config::{
+        DeviceFeature, DeviceFeatures, VulkanConfig, DESCRIPTOR_INDEXING_FEATURES,
+        VARIABLE_DESCRIPTOR_COUNT_FEATURES,
+    },
+};
 
 use super::{instance::Instance, physical_device::PhysicalDevice};
 
+/// Descriptor limits of a compute shader using update-after-bind sets, the lower of the per-set
+/// and per-stage limits.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct UpdateAfterBindLimits {
+    /// Sampled and storage images, including combined image samplers.
+    pub images: usize,
+    /// Samplers, including combined image samplers.
+    pub samplers: usize,
+    /// Descriptors of any type.
+    pub resources: usize,
+}
+
 pub struct Device {
     device: ash::Device,
+    /// The API version used, the lower of the requested and the supported version.
+    pub api_version: u32,
+    /// Enabled device features.
+    pub features: DeviceFeatures,
+    /// Whether runtime-sized, partially bound and non-uniformly indexed descriptor arrays can be
+    /// used (`VK_EXT_descriptor_indexing`, core in 1.2).
+    pub descriptor_indexing: bool,
+    /// Whether runtime-sized arrays can be allocated with a variable descriptor count from
+    /// update-after-bind pools.
+    pub variable_descriptor_count: bool,
+    /// Limits shared by all descriptors of a shader with variable-sized arrays, zero without
+    /// `variable_descriptor_count`.
+    pub update_after_bind_limits: UpdateAfterBindLimits,
+    /// Whether `VK_KHR_push_descriptor` is enabled.
+    pub push_descriptor: bool,
+    /// Whether pipeline statistics queries can be used.
+    pub pipeline_statistics: bool,
 }
 
 impl Deref for Device {
@@ -21,33 +56,204 @@ impl Deref for Device {
 }
 
 impl Device {
-    pub unsafe fn new(instance: &Instance, physical_device: &PhysicalDevice) -> VResult<Rc<Self>> {
+    pub unsafe fn new(
+        instance: &Instance,
+        physical_device: &PhysicalDevice,
+        config: &VulkanConfig,
+    ) -> VResult<Rc<Self>> {
         debug!("Creating device");
 
-        let compute_queue_create_info = vk::DeviceQueueCreateInfo::builder()
-            .queue_family_index(physical_device.compute_queue_family_index)
-            .queue_priorities(&[1.0])
-            .build();
+        let mut queue_family_indices = vec![physical_device.compute_queue_family_index];
+        if physical_device.present_queue_family_index != physical_device.compute_queue_family_index
+        {
+            queue_family_indices.push(physical_device.present_queue_family_index);
+        }
+        let create_infos = queue_family_indices
+            .iter()
+            .map(|&queue_family_index| {
+                vk::DeviceQueueCreateInfo::builder()
+                    .queue_family_index(queue_family_index)
+                    .queue_priorities(&[1.0])
+                    .build()
+            })
+            .collect::<Vec<_>>();
 
-        let create_infos = &[compute_queue_create_info];
+        let supported_extensions =
+            instance.enumerate_device_extension_properties(**physical_device)?;
+        let is_supported = |name: &CStr| {
+            supported_extensions
+                .iter()
+                .any(|extension| CStr::from_ptr(extension.extension_name.as_ptr()) == name)
+        };
 
         let swapchain_extension = extensions::khr::Swapchain::name();
-        let push_constant_extension =
-            CStr::from_bytes_with_nul_unchecked(b"VK_KHR_push_descriptor\0");
-        let device_extension_names_raw = [
-            swapchain_extension.as_ptr(),
-            push_constant_extension.as_ptr(),
-        ];
-        let features = vk::PhysicalDeviceFeatures::default();
-
-        let device_create_info = vk::DeviceCreateInfo::builder()
-            .queue_create_infos(create_infos)
-            .enabled_extension_names(&device_extension_names_raw)
-            .enabled_features(&features);
+        let push_descriptor_extension = extensions::khr::PushDescriptor::name();
+        let mut device_extension_names_raw = vec![swapchain_extension.as_ptr()];
+
+        // Without push descriptors, all descriptor sets are allocated from pools.
+        let push_descriptor = is_supported(push_descriptor_extension);
+        if push_descriptor {
+            device_extension_names_raw.push(push_descriptor_extension.as_ptr());
+        } else {
+            warn!(
+                "{push_descriptor_extension:?} is not supported, falling back to descriptor pools"
+            );
+        }
+
+        // Requested extensions.
+        let missing_extensions = config
+            .device_extensions
+            .iter()
+            .filter(|name| !is_supported(name))
+            .map(|name| name.to_string_lossy())
+            .collect::<Vec<_>>();
+        if !missing_extensions.is_empty() {
+            let msg = format!(
+                "Device extensions are not supported: {}",
+                missing_extensions.join(", ")
+            );
+            return Err(Error::Local(msg));
+        }
+        device_extension_names_raw
+            .extend(config.device_extensions.iter().map(|name| name.as_ptr()));
+        for e in &device_extension_names_raw {
+            debug!("Enabled device extension {:?}", CStr::from_ptr(*e));
+        }
+
+        // Feature structs are only available if both instance and device support their version.
+        let properties = instance.get_physical_device_properties(**physical_device);
+        let api_version = instance.api_version.min(properties.api_version);
+
+        let unavailable_features = config
+            .features
+            .iter()
+            .filter(|feature| feature.api_version() > api_version)
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        if !unavailable_features.is_empty() {
+            let msg = format!(
+                "Device features require a higher API version than {}.{}: {}",
+                vk::api_version_major(api_version),
+                vk::api_version_minor(api_version),
+                unavailable_features.join(", ")
+            );
+            return Err(Error::Local(msg));
+        }
+
+        // `vkGetPhysicalDeviceFeatures2` is core in 1.1.
+        let mut supported_features = DeviceFeatures::default();
+        if api_version >= vk::API_VERSION_1_1 {
+            let mut supported_features_chain = supported_features.chain(api_version);
+            instance
+                .get_physical_device_features2(**physical_device, &mut supported_features_chain);
+            supported_features.core = supported_features_chain.features;
+            supported_features.unchain();
+        } else {
+            supported_features.core = instance.get_physical_device_features(**physical_device);
+        }
+        let is_feature_supported = |feature: &DeviceFeature| {
+            feature.api_version() <= api_version && supported_features.contains(*feature)
+        };
+
+        // Requested features.
+        let missing_features = config
+            .features
+            .iter()
+            .filter(|feature| !is_feature_supported(feature))
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        if !missing_features.is_empty() {
+            let msg = format!(
+                "Device features are not supported: {}",
+                missing_features.join(", ")
+            );
+            return Err(Error::Local(msg));
+        }
+
+        let mut features = DeviceFeatures::default();
+        for feature in &config.features {
+            debug!("Enabled device feature {feature}");
+            features.insert(*feature);
+        }
+
+        // Descriptor indexing, required for descriptor arrays and bindless resource tables.
+        let descriptor_indexing = DESCRIPTOR_INDEXING_FEATURES
+            .iter()
+            .all(is_feature_supported);
+        if descriptor_indexing {
+            for feature in DESCRIPTOR_INDEXING_FEATURES {
+                features.insert(feature);
+            }
+        } else {
+            warn!("Descriptor indexing is not supported, runtime-sized arrays are unavailable");
+        }
+
+        // Variable descriptor counts, which lift the size of runtime arrays to the limits of
+        // update-after-bind sets.
+        let variable_descriptor_count = descriptor_indexing
+            && VARIABLE_DESCRIPTOR_COUNT_FEATURES
+                .iter()
+                .all(is_feature_supported);
+        let update_after_bind_limits = if variable_descriptor_count {
+            for feature in VARIABLE_DESCRIPTOR_COUNT_FEATURES {
+                features.insert(feature);
+            }
+            let mut vulkan_12_properties = vk::PhysicalDeviceVulkan12Properties::default();
+            let mut properties =
+                vk::PhysicalDeviceProperties2::builder().push_next(&mut vulkan_12_properties);
+            instance.get_physical_device_properties2(**physical_device, &mut properties);
+            let p = vulkan_12_properties;
+            let min = |limits: &[u32]| usize::try_from(*limits.iter().min().unwrap()).unwrap();
+            UpdateAfterBindLimits {
+                images: min(&[
+                    p.max_descriptor_set_update_after_bind_sampled_images,
+                    p.max_descriptor_set_update_after_bind_storage_images,
+                    p.max_per_stage_descriptor_update_after_bind_sampled_images,
+                    p.max_per_stage_descriptor_update_after_bind_storage_images,
+                ]),
+                samplers: min(&[
+                    p.max_descriptor_set_update_after_bind_samplers,
+                    p.max_per_stage_descriptor_update_after_bind_samplers,
+                ]),
+                resources: min(&[p.max_per_stage_update_after_bind_resources]),
+            }
+        } else {
+            UpdateAfterBindLimits::default()
+        };
+
+        // Pipeline statistics, used to count shader invocations.
+        let pipeline_statistics = config.pipeline_statistics
+            && is_feature_supported(&DeviceFeature::PipelineStatisticsQuery);
+        if pipeline_statistics {
+            features.insert(DeviceFeature::PipelineStatisticsQuery);
+        } else if config.pipeline_statistics {
+            warn!("Pipeline statistics queries are not supported, invocations won't be counted");
+        }
+
+        let mut features_chain = features.chain(api_version);
+
+        let mut device_create_info = vk::DeviceCreateInfo::builder()
+            .queue_create_infos(&create_infos)
+            .enabled_extension_names(&device_extension_names_raw);
+        device_create_info = if api_version >= vk::API_VERSION_1_1 {
+            device_create_info.push_next(&mut features_chain)
+        } else {
+            device_create_info.enabled_features(&features.core)
+        };
 
         let device = instance.create_device(**physical_device, &device_create_info, None)?;
+        features.unchain();
 
-        Ok(Rc::new(Self { device }))
+        Ok(Rc::new(Self {
+            device,
+            api_version,
+            features,
+            descriptor_indexing,
+            variable_descriptor_count,
+            update_after_bind_limits,
+            push_descriptor,
+            pipeline_statistics,
+        }))
     }
 }
 
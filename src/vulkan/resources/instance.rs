@@ -1,13 +1,57 @@
-use std::{ffi::CStr, ops::Deref, rc::Rc};
+use std::{
+    ffi::{c_void, CStr, CString},
+    ops::Deref,
+    rc::Rc,
+    sync::atomic::{AtomicUsize, Ordering},
+};
 
-use log::debug;
+use log::{debug, error, trace, warn};
 
-use ash::{self, vk};
+use ash::{self, extensions::ext::DebugUtils, vk};
 
-use crate::{error::VResult, window::Window};
+use crate::{
+    error::{Error, VResult},
+    vulkan::config::{ColorSpace, VulkanConfig},
+    window::Window,
+};
+
+/// Forward debug utils messages to `log` by severity. Errors are counted in `user_data`.
+unsafe extern "system" fn debug_utils_callback(
+    message_severity: vk::DebugUtilsMessageSeverityFlagsEXT,
+    message_type: vk::DebugUtilsMessageTypeFlagsEXT,
+    callback_data: *const vk::DebugUtilsMessengerCallbackDataEXT,
+    user_data: *mut c_void,
+) -> vk::Bool32 {
+    let callback_data = &*callback_data;
+    let message = if callback_data.p_message.is_null() {
+        "".into()
+    } else {
+        CStr::from_ptr(callback_data.p_message).to_string_lossy()
+    };
+
+    match message_severity {
+        vk::DebugUtilsMessageSeverityFlagsEXT::ERROR => {
+            error!("[{message_type:?}] {message}");
+            if let Some(validation_errors) = user_data.cast::<AtomicUsize>().as_ref() {
+                validation_errors.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        vk::DebugUtilsMessageSeverityFlagsEXT::WARNING => warn!("[{message_type:?}] {message}"),
+        vk::DebugUtilsMessageSeverityFlagsEXT::INFO => debug!("[{message_type:?}] {message}"),
+        _ => trace!("[{message_type:?}] {message}"),
+    }
+
+    vk::FALSE
+}
 
 pub struct Instance {
     pub instance: ash::Instance,
+    /// The requested API version, lowered to the version of the loader.
+    pub api_version: u32,
+    pub debug_utils: Option<DebugUtils>,
+    debug_utils_messenger: vk::DebugUtilsMessengerEXT,
+    validation_errors: Box<AtomicUsize>,
+    panic_on_validation_error: bool,
 }
 
 impl Deref for Instance {
@@ -19,33 +63,174 @@ impl Deref for Instance {
 }
 
 impl Instance {
-    pub unsafe fn new(window: &Window, entry: &ash::Entry) -> VResult<Rc<Self>> {
+    pub unsafe fn new(
+        window: &Window,
+        entry: &ash::Entry,
+        config: &VulkanConfig,
+    ) -> VResult<Rc<Self>> {
         debug!("Creating instance");
-        let app_info = vk::ApplicationInfo::builder().api_version(vk::make_api_version(0, 1, 3, 0));
+        // 1.0 loaders don't report their version and reject higher versions.
+        let loader_version = entry
+            .try_enumerate_instance_version()?
+            .unwrap_or(vk::API_VERSION_1_0);
+        let api_version = config.api_version.min(loader_version);
+        if api_version < config.api_version {
+            warn!(
+                "Vulkan {}.{} requested, but the loader only supports {}.{}",
+                vk::api_version_major(config.api_version),
+                vk::api_version_minor(config.api_version),
+                vk::api_version_major(api_version),
+                vk::api_version_minor(api_version),
+            );
+        }
+        let app_info = vk::ApplicationInfo::builder().api_version(api_version);
 
         // Instance extensions.
-        let extension_names = window.enumerate_required_extensions()?;
+        let available_extensions = entry.enumerate_instance_extension_properties(None)?;
+        let missing_extensions = config
+            .instance_extensions
+            .iter()
+            .filter(|name| {
+                !available_extensions.iter().any(|extension| {
+                    CStr::from_ptr(extension.extension_name.as_ptr()) == name.as_c_str()
+                })
+            })
+            .map(|name| name.to_string_lossy())
+            .collect::<Vec<_>>();
+        if !missing_extensions.is_empty() {
+            let msg = format!(
+                "Instance extensions are not supported: {}",
+                missing_extensions.join(", ")
+            );
+            return Err(Error::Local(msg));
+        }
+
+        let mut extension_names = window.enumerate_required_extensions()?;
+        extension_names.extend(config.instance_extensions.iter().map(|name| name.as_ptr()));
+
+        // Debug utils, used to log validation messages and name objects.
+        let debug_utils_available = available_extensions.iter().any(|extension| {
+            CStr::from_ptr(extension.extension_name.as_ptr()) == DebugUtils::name()
+        });
+        let use_debug_utils = config.debug_utils && debug_utils_available;
+        if config.debug_utils && !debug_utils_available {
+            warn!(
+                "Debug utils requested, but {:?} is not supported",
+                DebugUtils::name()
+            );
+        }
+        if use_debug_utils {
+            extension_names.push(DebugUtils::name().as_ptr());
+        }
+
+        // Non-sRGB color spaces are only reported with the swapchain color space extension.
+        let swapchain_colorspace_name = vk::ExtSwapchainColorspaceFn::name();
+        if config.color_space != ColorSpace::Srgb {
+            if available_extensions.iter().any(|extension| {
+                CStr::from_ptr(extension.extension_name.as_ptr()) == swapchain_colorspace_name
+            }) {
+                extension_names.push(swapchain_colorspace_name.as_ptr());
+            } else {
+                warn!(
+                    "{:?} requested, but {swapchain_colorspace_name:?} is not supported",
+                    config.color_space
+                );
+            }
+        }
+
         for e in &extension_names {
             debug!("Enabled instance extension {:?}", CStr::from_ptr(*e));
         }
 
-        // List available layers. TODO check that the validation layer exists.
-        // let layer_properties = entry
-        //     .enumerate_instance_layer_properties()?;
+        // Only enable the validation layer if it is installed.
         let validation_layer =
             CStr::from_bytes_with_nul_unchecked(b"VK_LAYER_KHRONOS_validation\0");
-        let layer_names = [validation_layer.as_ptr()];
+        let layer_properties = entry.enumerate_instance_layer_properties()?;
+        let validation_available = layer_properties
+            .iter()
+            .any(|layer| CStr::from_ptr(layer.layer_name.as_ptr()) == validation_layer);
+
+        let mut layer_names = Vec::new();
+        if config.validation {
+            if validation_available {
+                layer_names.push(validation_layer.as_ptr());
+            } else {
+                warn!("Validation requested, but {validation_layer:?} is not installed");
+            }
+        }
         for l in &layer_names {
             debug!("Enabled layer {:?}", CStr::from_ptr(*l));
         }
 
-        let create_info = vk::InstanceCreateInfo::builder()
+        let validation_errors = Box::new(AtomicUsize::new(0));
+        let mut messenger_create_info = vk::DebugUtilsMessengerCreateInfoEXT::builder()
+            .message_severity(
+                vk::DebugUtilsMessageSeverityFlagsEXT::ERROR
+                    | vk::DebugUtilsMessageSeverityFlagsEXT::WARNING
+                    | vk::DebugUtilsMessageSeverityFlagsEXT::INFO,
+            )
+            .message_type(
+                vk::DebugUtilsMessageTypeFlagsEXT::GENERAL
+                    | vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION
+                    | vk::DebugUtilsMessageTypeFlagsEXT::PERFORMANCE,
+            )
+            .pfn_user_callback(Some(debug_utils_callback))
+            .user_data(std::ptr::addr_of!(*validation_errors).cast_mut().cast());
+
+        let mut create_info = vk::InstanceCreateInfo::builder()
             .application_info(&app_info)
             .enabled_extension_names(&extension_names)
             .enabled_layer_names(&layer_names);
+        // Also catch messages during instance creation and destruction.
+        if use_debug_utils {
+            create_info = create_info.push_next(&mut messenger_create_info);
+        }
 
         let instance = entry.create_instance(&create_info, None)?;
-        Ok(Rc::new(Self { instance }))
+
+        let (debug_utils, debug_utils_messenger) = if use_debug_utils {
+            let debug_utils = DebugUtils::new(entry, &instance);
+            let messenger =
+                debug_utils.create_debug_utils_messenger(&messenger_create_info, None)?;
+            (Some(debug_utils), messenger)
+        } else {
+            (None, vk::DebugUtilsMessengerEXT::null())
+        };
+
+        Ok(Rc::new(Self {
+            instance,
+            api_version,
+            debug_utils,
+            debug_utils_messenger,
+            validation_errors,
+            panic_on_validation_error: config.panic_on_validation_error,
+        }))
+    }
+}
+
+impl Instance {
+    /// Label `object` with `name` in validation messages and debuggers such as ngfx.
+    pub unsafe fn set_object_name<H: vk::Handle>(&self, device: vk::Device, object: H, name: &str) {
+        let Some(debug_utils) = &self.debug_utils else {
+            return;
+        };
+
+        let name = CString::new(name).expect("Object names must not contain NUL");
+        let name_info = vk::DebugUtilsObjectNameInfoEXT::builder()
+            .object_type(H::TYPE)
+            .object_handle(object.as_raw())
+            .object_name(&name);
+        if let Err(err) = debug_utils.set_debug_utils_object_name(device, &name_info) {
+            warn!("Failed to name object {name:?}: {err}");
+        }
+    }
+
+    /// Panic if validation errors were reported and `panic_on_validation_error` is set.
+    pub fn check_validation_errors(&self) {
+        let validation_errors = self.validation_errors.swap(0, Ordering::Relaxed);
+        if self.panic_on_validation_error && validation_errors > 0 {
+            panic!("{validation_errors} validation error(s) occurred");
+        }
     }
 }
 
@@ -53,6 +238,9 @@ impl Drop for Instance {
     fn drop(&mut self) {
         debug!("Destroying instance");
         unsafe {
+            if let Some(debug_utils) = &self.debug_utils {
+                debug_utils.destroy_debug_utils_messenger(self.debug_utils_messenger, None);
+            }
             self.destroy_instance(None);
         }
     }
@@ -2,6 +2,7 @@ pub mod buffer;
 pub mod command_buffer;
 pub mod command_pool;
 pub mod descriptor_layout;
+pub mod descriptor_pool;
 pub mod descriptors;
 pub mod device;
 pub mod device_memory;
@@ -13,6 +14,7 @@ pub mod memory_mapping;
 pub mod physical_device;
 pub mod pipeline;
 pub mod pipeline_layout;
+pub mod query_pool;
 pub mod sampler;
 pub mod semaphore;
 pub mod shader_module;
@@ -1,57 +1,177 @@
-use std::{ops::Deref, rc::Rc};
+use std::{ffi::CStr, fmt::Display, ops::Deref, rc::Rc};
 
 use core::ops::Not;
 
-use log::debug;
+use log::{debug, info};
 
-use ash::vk;
+use ash::{extensions::khr::Surface as SurfaceLoader, vk};
 
-use crate::error::{Error, VResult};
+use crate::{
+    error::{Error, VResult},
+    vulkan::config::VulkanConfig,
+    window::Window,
+};
 
 use super::{instance::Instance, surface::Surface};
 
-fn choose_compute_queue_family(
-    _physical_device: vk::PhysicalDevice,
-    index: u32,
-    queue_family_properties: &vk::QueueFamilyProperties,
-) -> Option<u32> {
-    let queue_flags = queue_family_properties.queue_flags;
-    let supports_compute = queue_flags.contains(vk::QueueFlags::COMPUTE);
-    let does_not_support_graphics = queue_flags.not().contains(vk::QueueFlags::GRAPHICS);
+/// Environment variable overriding the device selection, see `DeviceSelector::from_env`.
+pub const DEVICE_ENV_VAR: &str = "SHADE_DEVICE";
 
-    if supports_compute && does_not_support_graphics {
-        Some(index)
-    } else {
-        None
+/// How to pick the physical device.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeviceSelector {
+    /// Prefer discrete over integrated over virtual over CPU devices.
+    Auto,
+    /// The first device whose name contains the string (case insensitive).
+    Name(String),
+    /// The device at this position in `vkEnumeratePhysicalDevices`.
+    Index(usize),
+    /// The first device of this type.
+    Type(vk::PhysicalDeviceType),
+}
+
+impl DeviceSelector {
+    /// Parse `SHADE_DEVICE`: an index, one of `discrete`, `integrated`, `virtual`, `cpu`, or a
+    /// part of the device name.
+    #[must_use]
+    pub fn from_env() -> Option<Self> {
+        let value = std::env::var(DEVICE_ENV_VAR).ok()?;
+        let selector = if let Ok(index) = value.parse() {
+            Self::Index(index)
+        } else {
+            match value.to_lowercase().as_str() {
+                "discrete" => Self::Type(vk::PhysicalDeviceType::DISCRETE_GPU),
+                "integrated" => Self::Type(vk::PhysicalDeviceType::INTEGRATED_GPU),
+                "virtual" => Self::Type(vk::PhysicalDeviceType::VIRTUAL_GPU),
+                "cpu" => Self::Type(vk::PhysicalDeviceType::CPU),
+                _ => Self::Name(value),
+            }
+        };
+        Some(selector)
+    }
+
+    fn matches(&self, info: &PhysicalDeviceInfo) -> bool {
+        match self {
+            Self::Auto => true,
+            Self::Name(name) => info.name.to_lowercase().contains(&name.to_lowercase()),
+            Self::Index(index) => info.index == *index,
+            Self::Type(device_type) => info.properties.device_type == *device_type,
+        }
     }
 }
 
-unsafe fn choose_physical_device_queue(
-    instance: &Instance,
-    _surface: &Surface,
+fn device_type_rank(device_type: vk::PhysicalDeviceType) -> usize {
+    match device_type {
+        vk::PhysicalDeviceType::DISCRETE_GPU => 0,
+        vk::PhysicalDeviceType::INTEGRATED_GPU => 1,
+        vk::PhysicalDeviceType::VIRTUAL_GPU => 2,
+        vk::PhysicalDeviceType::CPU => 3,
+        _ => 4,
+    }
+}
+
+/// Prefer a compute-only (async compute) queue family, fall back to a graphics and compute
+/// family. Families which can present to `surface` are preferred, otherwise a separate present
+/// family is returned alongside the compute family.
+unsafe fn choose_queue_families(
+    surface: &Surface,
     physical_device: vk::PhysicalDevice,
-) -> Option<(vk::PhysicalDevice, u32)> {
-    let queue_family_properties =
-        instance.get_physical_device_queue_family_properties(physical_device);
+    queue_family_properties: &[vk::QueueFamilyProperties],
+) -> VResult<(Option<u32>, Option<u32>)> {
+    let mut compute_only_present = None;
+    let mut compute_present = None;
+    let mut compute_only = None;
+    let mut compute = None;
+    let mut present = None;
+    for (index, properties) in queue_family_properties.iter().enumerate() {
+        let index = u32::try_from(index).unwrap();
+        let queue_flags = properties.queue_flags;
+        let supports_compute = queue_flags.contains(vk::QueueFlags::COMPUTE);
+        let supports_present = surface.supports_present(physical_device, index)?;
 
-    let compute_queue_family_index =
-        queue_family_properties
-            .iter()
-            .enumerate()
-            .find_map(|(index, queue_family_props)| {
-                choose_compute_queue_family(
-                    physical_device,
-                    u32::try_from(index).unwrap(),
-                    queue_family_props,
-                )
-            })?;
-
-    Some((physical_device, compute_queue_family_index))
+        if supports_present {
+            present = present.or(Some(index));
+        }
+        if !supports_compute {
+            continue;
+        }
+        compute = compute.or(Some(index));
+        let compute_only_family = queue_flags.not().contains(vk::QueueFlags::GRAPHICS);
+        if compute_only_family {
+            compute_only = compute_only.or(Some(index));
+        }
+        if supports_present {
+            if compute_only_family {
+                compute_only_present = compute_only_present.or(Some(index));
+            }
+            compute_present = compute_present.or(Some(index));
+        }
+    }
+
+    if let Some(index) = compute_only_present.or(compute_present) {
+        return Ok((Some(index), Some(index)));
+    }
+    Ok((compute_only.or(compute), present))
+}
+
+/// List the physical devices which can render to `window`, e.g. to pick a `DeviceSelector`
+/// before creating `Vulkan`. Only creates a temporary instance and surface with `config`.
+pub fn enumerate(window: &Window, config: &VulkanConfig) -> VResult<Vec<PhysicalDeviceInfo>> {
+    unsafe {
+        let entry = ash::Entry::linked();
+        let instance = Instance::new(window, &entry, config)?;
+        let surface_loader = SurfaceLoader::new(&entry, &instance);
+        let surface = Surface::new(window, &entry, &instance, &surface_loader)?;
+        let physical_devices = PhysicalDevice::enumerate(&instance, &surface)?;
+        Ok(physical_devices.into_iter().map(|(_, info)| info).collect())
+    }
+}
+
+/// Properties of a physical device, as listed by `enumerate`.
+#[derive(Debug, Clone)]
+pub struct PhysicalDeviceInfo {
+    /// Position in `vkEnumeratePhysicalDevices`.
+    pub index: usize,
+    pub name: String,
+    /// Includes the device type, API version and limits.
+    pub properties: vk::PhysicalDeviceProperties,
+    /// The queue family used for compute, `None` if the device is unsuitable.
+    pub compute_queue_family_index: Option<u32>,
+    /// The queue family used for present, `None` if the device cannot present to the surface.
+    pub present_queue_family_index: Option<u32>,
+    /// Number of meaningful bits in timestamps written on the compute queue, 0 if unsupported.
+    pub timestamp_valid_bits: u32,
+}
+
+impl PhysicalDeviceInfo {
+    #[must_use]
+    pub fn limits(&self) -> &vk::PhysicalDeviceLimits {
+        &self.properties.limits
+    }
+}
+
+impl Display for PhysicalDeviceInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let api_version = self.properties.api_version;
+        write!(
+            f,
+            "[{}] {} ({:?}, Vulkan {}.{}.{})",
+            self.index,
+            self.name,
+            self.properties.device_type,
+            vk::api_version_major(api_version),
+            vk::api_version_minor(api_version),
+            vk::api_version_patch(api_version),
+        )
+    }
 }
 
 pub struct PhysicalDevice {
     physical_device: vk::PhysicalDevice,
+    pub info: PhysicalDeviceInfo,
     pub compute_queue_family_index: u32,
+    /// Differs from `compute_queue_family_index` if the compute queue cannot present.
+    pub present_queue_family_index: u32,
     pub buffer_memory_type_index: u32,
     pub image_memory_type_index: u32,
 }
@@ -87,14 +207,81 @@ fn choose_image_memory_type(index: u32, memory_type: vk::MemoryType) -> Option<u
 }
 
 impl PhysicalDevice {
-    pub unsafe fn new(instance: &Instance, surface: &Surface) -> VResult<Rc<Self>> {
+    /// List all physical devices with their properties.
+    pub unsafe fn enumerate(
+        instance: &Instance,
+        surface: &Surface,
+    ) -> VResult<Vec<(vk::PhysicalDevice, PhysicalDeviceInfo)>> {
+        instance
+            .enumerate_physical_devices()?
+            .into_iter()
+            .enumerate()
+            .map(|(index, physical_device)| {
+                let properties = instance.get_physical_device_properties(physical_device);
+                let name = CStr::from_ptr(properties.device_name.as_ptr())
+                    .to_string_lossy()
+                    .into_owned();
+                let queue_family_properties =
+                    instance.get_physical_device_queue_family_properties(physical_device);
+                let (compute_queue_family_index, present_queue_family_index) =
+                    choose_queue_families(surface, physical_device, &queue_family_properties)?;
+                let timestamp_valid_bits = compute_queue_family_index.map_or(0, |index| {
+                    queue_family_properties[index as usize].timestamp_valid_bits
+                });
+                let info = PhysicalDeviceInfo {
+                    index,
+                    name,
+                    properties,
+                    compute_queue_family_index,
+                    present_queue_family_index,
+                    timestamp_valid_bits,
+                };
+                Ok((physical_device, info))
+            })
+            .collect()
+    }
+
+    /// Choose a physical device using `selector`, which is overridden by the `SHADE_DEVICE`
+    /// environment variable.
+    pub unsafe fn new(
+        instance: &Instance,
+        surface: &Surface,
+        selector: &DeviceSelector,
+    ) -> VResult<Rc<Self>> {
         debug!("Choosing physical device");
 
-        let physical_devices = instance.enumerate_physical_devices()?;
-        let (physical_device, compute_queue_family_index) = physical_devices
+        let physical_devices = Self::enumerate(instance, surface)?;
+        for (_, info) in &physical_devices {
+            debug!("Found physical device {info}");
+        }
+
+        let selector = DeviceSelector::from_env().unwrap_or_else(|| selector.clone());
+        let mut suitable = physical_devices
             .into_iter()
-            .find_map(|p| choose_physical_device_queue(instance, surface, p))
-            .ok_or_else(|| Error::Local("Couldn't find suitable device".to_owned()))?;
+            .filter(|(_, info)| selector.matches(info))
+            .filter(|(_, info)| {
+                info.compute_queue_family_index.is_some()
+                    && info.present_queue_family_index.is_some()
+            });
+        // Only `Auto` ranks the devices, the other selectors take the first match.
+        let chosen = if selector == DeviceSelector::Auto {
+            suitable.min_by_key(|(_, info)| device_type_rank(info.properties.device_type))
+        } else {
+            suitable.next()
+        };
+        let (physical_device, info) = chosen.ok_or_else(|| {
+            let msg = format!("Couldn't find suitable device matching {selector:?}");
+            Error::Local(msg)
+        })?;
+        let compute_queue_family_index = info.compute_queue_family_index.unwrap();
+        let present_queue_family_index = info.present_queue_family_index.unwrap();
+        info!("Using physical device {info}");
+        if compute_queue_family_index != present_queue_family_index {
+            info!(
+                "Compute queue family {compute_queue_family_index} cannot present, \
+                 using queue family {present_queue_family_index} for present"
+            );
+        }
 
         // For reference see: https://github.com/Traverse-Research/gpu-allocator/blob/main/src/vulkan/mod.rs#L742
         let memory_props = instance.get_physical_device_memory_properties(physical_device);
@@ -117,33 +304,11 @@ impl PhysicalDevice {
 
         Ok(Rc::new(Self {
             physical_device,
+            info,
             compute_queue_family_index,
+            present_queue_family_index,
             buffer_memory_type_index,
             image_memory_type_index,
         }))
     }
 }
-
-// fn choose_render_queue_family(
-//     surface: &Surface,
-//     physical_device: vk::PhysicalDevice,
-//     (index, queue_family_properties): (u32, &vk::QueueFamilyProperties),
-// ) -> Option<u32> {
-//     let supports_graphics = queue_family_properties
-//         .queue_flags
-//         .contains(vk::QueueFlags::GRAPHICS);
-//     let supports_surface = unsafe {
-//         surface.surface_loader.get_physical_device_surface_support(
-//             physical_device,
-//             index as u32,
-//             surface.surface,
-//         )
-//     }
-//     .expect("Failed to get physical device surface support info");
-//
-//     if supports_graphics && supports_surface {
-//         Some(index as u32)
-//     } else {
-//         None
-//     }
-// }
@@ -25,7 +25,7 @@ impl PipelineLayout {
     pub unsafe fn new(
         device: &Rc<Device>,
         shader_module: &ShaderModule,
-        descriptor_layout: &DescriptorLayout,
+        descriptor_layouts: &[Rc<DescriptorLayout>],
     ) -> VResult<Rc<Self>> {
         debug!("Creating pipeline layout");
         let device = device.clone();
@@ -51,7 +51,10 @@ impl PipelineLayout {
             );
         }
 
-        let layouts = [**descriptor_layout];
+        let layouts = descriptor_layouts
+            .iter()
+            .map(|descriptor_layout| ***descriptor_layout)
+            .collect::<Vec<_>>();
         let layout_create_info = vk::PipelineLayoutCreateInfo::builder()
             .push_constant_ranges(&push_constant_ranges)
             .set_layouts(&layouts);
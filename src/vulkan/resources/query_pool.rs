@@ -0,0 +1,71 @@
+use std::{ops::Deref, rc::Rc};
+
+use log::debug;
+
+use ash::vk;
+
+use crate::error::VResult;
+
+use super::device::Device;
+
+pub struct QueryPool {
+    device: Rc<Device>,
+    query_pool: vk::QueryPool,
+    pub query_count: u32,
+}
+
+impl Deref for QueryPool {
+    type Target = vk::QueryPool;
+
+    fn deref(&self) -> &Self::Target {
+        &self.query_pool
+    }
+}
+
+impl QueryPool {
+    pub unsafe fn new(
+        device: &Rc<Device>,
+        query_type: vk::QueryType,
+        pipeline_statistics: vk::QueryPipelineStatisticFlags,
+        query_count: u32,
+    ) -> VResult<Rc<Self>> {
+        debug!("Creating query pool");
+        let device = device.clone();
+        let create_info = vk::QueryPoolCreateInfo::builder()
+            .query_type(query_type)
+            .pipeline_statistics(pipeline_statistics)
+            .query_count(query_count);
+        let query_pool = device.create_query_pool(&create_info, None)?;
+        Ok(Rc::new(Self {
+            device,
+            query_pool,
+            query_count,
+        }))
+    }
+
+    /// Read `count` 64 bit results starting at `first` without waiting, `None` if they are not
+    /// available yet.
+    pub unsafe fn results(&self, first: u32, count: u32) -> VResult<Option<Vec<u64>>> {
+        let mut data = vec![0u64; count as usize];
+        match self.device.get_query_pool_results(
+            self.query_pool,
+            first,
+            count,
+            &mut data,
+            vk::QueryResultFlags::TYPE_64,
+        ) {
+            Ok(()) => Ok(Some(data)),
+            Err(vk::Result::NOT_READY) => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+impl Drop for QueryPool {
+    fn drop(&mut self) {
+        debug!("Destroying query pool");
+        unsafe {
+            self.device.destroy_query_pool(**self, None);
+        }
+    }
+}
@@ -1,6 +1,5 @@
 use glsl::{parser::Parse as _, syntax};
 use log::{info, warn};
-use std::{fs, path::Path};
 
 use ash::vk;
 
@@ -131,6 +130,13 @@ pub trait DescriptorInfo {
     fn name(&self) -> &str;
 }
 
+/// Size of a descriptor array, e.g. `textures[4]` or the runtime-sized `textures[]`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArraySize {
+    Fixed(usize),
+    Runtime,
+}
+
 #[derive(Debug)]
 pub struct VariableDeclaration {
     pub name: String,
@@ -138,6 +144,7 @@ pub struct VariableDeclaration {
     pub binding: Option<usize>,
     pub set: Option<usize>,
     pub image_format: Option<ImageFormat>,
+    pub array_size: Option<ArraySize>,
 }
 
 impl DescriptorInfo for VariableDeclaration {
@@ -152,11 +159,9 @@ impl DescriptorInfo for VariableDeclaration {
         }
     }
 
+    /// GLSL assigns declarations without an explicit set to set 0.
     fn set_index(&self) -> usize {
-        self.set.unwrap_or_else(|| {
-            warn!("Assuming set=0 for variable {}", self.name);
-            0
-        })
+        self.set.unwrap_or(0)
     }
 
     fn binding(&self) -> VResult<usize> {
@@ -199,6 +204,27 @@ fn simplify_type_specifier(
     Ok(ty.clone())
 }
 
+fn match_array_specifier(array_specifier: &syntax::ArraySpecifier) -> VResult<Vec<Option<usize>>> {
+    let syntax::ArraySpecifier {
+        dimensions: syntax::NonEmpty(dimensions),
+    } = array_specifier;
+    dimensions
+        .iter()
+        .map(|sizing| {
+            if let syntax::ArraySpecifierDimension::ExplicitlySized(expr_box) = sizing {
+                if let syntax::Expr::IntConst(value) = **expr_box {
+                    Ok(Some(usize::try_from(value).unwrap()))
+                } else {
+                    let msg = format!("Unexpected array dimension value: {:?}", **expr_box);
+                    Err(Error::Local(msg))
+                }
+            } else {
+                Ok(None)
+            }
+        })
+        .collect()
+}
+
 fn match_init_declarator_list(
     init_declarator_list: &syntax::InitDeclaratorList,
 ) -> VResult<Option<VariableDeclaration>> {
@@ -248,9 +274,21 @@ fn match_init_declarator_list(
         return Err(Error::Local(msg));
     };
 
-    if array_specifier.is_some() {
-        warn!("Unhandled array specifier: {array_specifier:?}");
-    }
+    // Descriptor arrays may only have a single dimension.
+    let array_size = array_specifier
+        .as_ref()
+        .map(
+            |array_specifier| match match_array_specifier(array_specifier)?[..] {
+                [Some(size)] => Ok(ArraySize::Fixed(size)),
+                [None] => Ok(ArraySize::Runtime),
+                _ => {
+                    let msg =
+                        format!("Unexpected array dimensions for {name}: {array_specifier:?}");
+                    Err(Error::Local(msg))
+                }
+            },
+        )
+        .transpose()?;
 
     if initializer.is_some() {
         warn!("Unhandled initializer: {initializer:?}");
@@ -267,6 +305,7 @@ fn match_init_declarator_list(
         binding: type_properties.binding,
         set: type_properties.set,
         image_format: type_properties.image_format,
+        array_size,
     }))
 }
 
@@ -281,7 +320,8 @@ pub struct BlockField {
 impl BlockField {
     // We will check for dimensions and then this will be None-able.
     #[allow(clippy::unnecessary_wraps)]
-    fn byte_size(&self) -> Option<usize> {
+    #[must_use]
+    pub fn byte_size(&self) -> Option<usize> {
         #[allow(clippy::match_same_arms)]
         Some(match &self.type_specifier {
             syntax::TypeSpecifierNonArray::Void => 1,
@@ -335,29 +375,7 @@ fn match_block_field(block_field: &syntax::StructFieldSpecifier) -> VResult<Bloc
         }
     };
     let syntax::ArrayedIdentifier { ident, array_spec } = arrayed_identifier;
-    let dimensions = array_spec
-        .as_ref()
-        .map(|array_specifier| {
-            let syntax::ArraySpecifier {
-                dimensions: syntax::NonEmpty(dimensions),
-            } = array_specifier;
-            dimensions
-                .iter()
-                .map(|sizing| {
-                    if let syntax::ArraySpecifierDimension::ExplicitlySized(expr_box) = sizing {
-                        if let syntax::Expr::IntConst(value) = **expr_box {
-                            Ok(Some(usize::try_from(value).unwrap()))
-                        } else {
-                            let msg = format!("Unexpected array dimension value: {:?}", **expr_box);
-                            Err(Error::Local(msg))
-                        }
-                    } else {
-                        Ok(None)
-                    }
-                })
-                .collect::<VResult<Vec<Option<usize>>>>()
-        })
-        .transpose()?;
+    let dimensions = array_spec.as_ref().map(match_array_specifier).transpose()?;
 
     Ok(BlockField {
         name: ident.to_string(),
@@ -383,11 +401,9 @@ impl DescriptorInfo for BlockDeclaration {
         self.storage
     }
 
+    /// GLSL assigns declarations without an explicit set to set 0.
     fn set_index(&self) -> usize {
-        self.set.unwrap_or_else(|| {
-            warn!("Assuming set=0 for block {}", self.struct_name);
-            0 // TODO move this to parsing stage.
-        })
+        self.set.unwrap_or(0)
     }
 
     fn binding(&self) -> VResult<usize> {
@@ -472,10 +488,7 @@ fn match_block(block: &syntax::Block) -> VResult<BlockDeclaration> {
 pub type LocalSize = (usize, usize, usize);
 pub type ShaderIO = (LocalSize, Vec<VariableDeclaration>, Vec<BlockDeclaration>);
 
-pub fn analyze_shader(path: &Path) -> VResult<ShaderIO> {
-    let shader_code = fs::read_to_string(path).map_err(|err| {
-        Error::Local(format!("File '{}' cannot be read: {err:?}", path.display()))
-    })?;
+pub fn analyze_shader(shader_code: &str) -> VResult<ShaderIO> {
     let syntax::TranslationUnit(syntax::NonEmpty(external_declarations)) =
         syntax::ShaderStage::parse(shader_code)?;
 
@@ -1,4 +1,4 @@
-use log::{debug, error};
+use log::debug;
 use shaderc::CompileOptions;
 use std::{
     fmt::Display,
@@ -18,28 +18,27 @@ use super::device::Device;
 
 pub mod analysis;
 
-fn compile_shader_file(file: &Path) -> VResult<shaderc::CompilationArtifact> {
+/// Compile GLSL `source`. `file_name` prefixes the lines of error messages.
+fn compile_shader(source: &str, file_name: &str) -> VResult<shaderc::CompilationArtifact> {
     const MAGIC_NUMBER: u32 = 0x0723_0203;
 
-    let source = fs::read_to_string(file)?;
     let compiler = shaderc::Compiler::new()
         .ok_or_else(|| Error::Local("Failed to create shaderc compiler".to_owned()))?;
 
-    let file_name = file.file_name().unwrap().to_str().unwrap();
     let mut compile_options = CompileOptions::new().unwrap();
     compile_options.set_generate_debug_info();
     let binary = compiler
         .compile_into_spirv(
-            &source,
+            source,
             shaderc::ShaderKind::Compute,
             file_name,
             "main",
             Some(&compile_options),
         )
         .map_err(|err| match err {
+            // The message lists the errors as `file:line: error: ...`.
             shaderc::Error::CompilationError(_, msg) => {
-                error!("Shader compilation failed\n{}", msg);
-                Error::Local("Shader compilation failed".to_owned())
+                Error::Local(format!("Shader compilation failed\n{}", msg.trim_end()))
             }
             _ => Error::Shaderc(err),
         })?;
@@ -82,6 +81,7 @@ impl Display for ShaderModule {
             writeln!(f, "      Type:    {:?}", declaration.storage())?;
             writeln!(f, "      Set:     {:?}", declaration.set)?;
             writeln!(f, "      Binding: {:?}", declaration.binding)?;
+            writeln!(f, "      Array:   {:?}", declaration.array_size)?;
         }
         writeln!(f, "  Block Declarations:")?;
         for declaration in &self.block_declarations {
@@ -96,15 +96,32 @@ impl Display for ShaderModule {
 
 impl ShaderModule {
     pub unsafe fn new(device: &Rc<Device>, source_path: &Path) -> VResult<Rc<Self>> {
-        debug!("Creating shader module");
-        let (local_size, variable_declarations, block_declarations) =
-            analysis::analyze_shader(source_path)?;
+        let source = fs::read_to_string(source_path).map_err(|err| {
+            let msg = format!("File '{}' cannot be read: {err:?}", source_path.display());
+            Error::Local(msg)
+        })?;
+        Self::from_source(device, source_path, &source)
+    }
 
+    /// Create a shader module from GLSL `source`, e.g. of a built-in pass. `source_path` only
+    /// names the shader.
+    pub unsafe fn from_source(
+        device: &Rc<Device>,
+        source_path: &Path,
+        source: &str,
+    ) -> VResult<Rc<Self>> {
+        debug!("Creating shader module");
         let device = device.clone();
         let source_path = source_path.to_path_buf();
 
+        // Compile first, shaderc reports errors with their line.
         debug!("Compiling shader");
-        let shader_content = compile_shader_file(&source_path)?;
+        let file_name = source_path.file_name().unwrap().to_str().unwrap();
+        let shader_content = compile_shader(source, file_name)?;
+
+        let (local_size, variable_declarations, block_declarations) =
+            analysis::analyze_shader(source)?;
+
         let shader_info = vk::ShaderModuleCreateInfo::builder().code(shader_content.as_binary());
         let shader_module = device.create_shader_module(&shader_info, None)?;
         let main_name = "main".to_owned();
@@ -39,6 +39,22 @@ impl Surface {
     }
 }
 
+impl Surface {
+    /// Whether the queue family `queue_family_index` of `physical_device` can present to this
+    /// surface.
+    pub unsafe fn supports_present(
+        &self,
+        physical_device: vk::PhysicalDevice,
+        queue_family_index: u32,
+    ) -> VResult<bool> {
+        Ok(self.surface_loader.get_physical_device_surface_support(
+            physical_device,
+            queue_family_index,
+            self.surface,
+        )?)
+    }
+}
+
 impl Drop for Surface {
     fn drop(&mut self) {
         debug!("Destroying surface");
@@ -1,26 +1,100 @@
-use log::{debug, error, warn};
+use log::{debug, warn};
 
 use ash::{extensions::khr::Surface as SurfaceLoader, vk};
 
-use crate::error::{Error, VResult};
+use crate::{
+    error::{Error, VResult},
+    vulkan::config::{ColorSpace, PresentMode, PresentStrategy, VulkanConfig},
+};
 
-use super::{physical_device::PhysicalDevice, surface::Surface};
+use super::{instance::Instance, physical_device::PhysicalDevice, surface::Surface};
+
+/// The swapchain image usage and format features `present_strategy` relies on.
+fn required_support(
+    present_strategy: PresentStrategy,
+) -> (vk::ImageUsageFlags, vk::FormatFeatureFlags) {
+    match present_strategy {
+        PresentStrategy::Blit => (
+            vk::ImageUsageFlags::TRANSFER_DST,
+            vk::FormatFeatureFlags::BLIT_DST,
+        ),
+        PresentStrategy::Auto | PresentStrategy::Direct => (
+            vk::ImageUsageFlags::STORAGE | vk::ImageUsageFlags::SAMPLED,
+            vk::FormatFeatureFlags::STORAGE_IMAGE,
+        ),
+    }
+}
+
+/// Whether shaders can declare a format qualifier matching `format`. GLSL has none for BGR
+/// ordered formats, writing them needs `shaderStorageImageWriteWithoutFormat`.
+fn has_format_qualifier(format: vk::Format) -> bool {
+    !matches!(
+        format,
+        vk::Format::B8G8R8A8_UNORM | vk::Format::A2R10G10B10_UNORM_PACK32
+    )
+}
+
+/// Pick the first format of `preferred_formats` the surface offers in `color_space` which can be
+/// written using `present_strategy`.
+unsafe fn choose_surface_format(
+    instance: &Instance,
+    physical_device: &PhysicalDevice,
+    surface_formats: &[vk::SurfaceFormatKHR],
+    surface_capabilities: &vk::SurfaceCapabilitiesKHR,
+    preferred_formats: &[vk::Format],
+    color_space: ColorSpace,
+    present_strategy: PresentStrategy,
+) -> Option<vk::SurfaceFormatKHR> {
+    let (usage, format_features) = required_support(present_strategy);
+    if !surface_capabilities.supported_usage_flags.contains(usage) {
+        return None;
+    }
+
+    preferred_formats.iter().find_map(|&format| {
+        if present_strategy != PresentStrategy::Blit && !has_format_qualifier(format) {
+            return None;
+        }
+        let supported = instance
+            .get_physical_device_format_properties(**physical_device, format)
+            .optimal_tiling_features
+            .contains(format_features);
+        surface_formats
+            .iter()
+            .find(|surface_format| {
+                surface_format.format == format
+                    && surface_format.color_space == color_space.vk_color_space()
+            })
+            .filter(|_| supported)
+            .copied()
+    })
+}
 
 #[derive(Debug)]
 pub struct SurfaceInfo {
     pub surface_format: vk::SurfaceFormatKHR,
+    pub color_space: ColorSpace,
+    /// Either `Direct` or `Blit`, never `Auto`.
+    pub present_strategy: PresentStrategy,
     pub surface_capabilities: vk::SurfaceCapabilitiesKHR,
-    pub desired_present_mode: vk::PresentModeKHR,
+    /// The first supported mode of `config.present_mode.fallbacks()`.
+    pub present_mode: PresentMode,
+    pub supported_present_modes: Vec<PresentMode>,
+    pub composite_alpha: vk::CompositeAlphaFlagsKHR,
     pub desired_image_count: usize,
     pub surface_resolution: vk::Extent2D,
+    /// Resolution shaders render at, differs from `surface_resolution` only with
+    /// `PresentStrategy::Blit`.
+    pub render_resolution: vk::Extent2D,
 }
 
 impl SurfaceInfo {
     pub unsafe fn new(
+        instance: &Instance,
         physical_device: &PhysicalDevice,
         surface_loader: &SurfaceLoader,
         surface: &Surface,
-        vsync: bool,
+        config: &VulkanConfig,
+        window_extent: vk::Extent2D,
     ) -> VResult<Self> {
         debug!("Collecting surface info");
 
@@ -31,30 +105,112 @@ impl SurfaceInfo {
         let present_modes = surface_loader
             .get_physical_device_surface_present_modes(**physical_device, **surface)?;
 
-        let surface_format = surface_formats[0];
+        for surface_format in &surface_formats {
+            debug!(
+                "Found surface format {:?} {:?}",
+                surface_format.format, surface_format.color_space
+            );
+        }
+
+        let surface_resolution = Self::surface_resolution(&surface_capabilities, window_extent);
+        let render_resolution = config.render_resolution.extent(surface_resolution);
+        let scaled = render_resolution != surface_resolution;
+
+        // The requested color space takes precedence over writing to the swapchain directly.
+        let mut color_spaces = vec![config.color_space];
+        if config.color_space != ColorSpace::Srgb {
+            color_spaces.push(ColorSpace::Srgb);
+        }
+        let present_strategies = match config.present_strategy {
+            PresentStrategy::Direct if scaled => {
+                return Err(Error::Local(
+                    "Render resolution scaling requires the blit present strategy".to_owned(),
+                ));
+            }
+            PresentStrategy::Auto if scaled => vec![PresentStrategy::Blit],
+            PresentStrategy::Auto => vec![PresentStrategy::Direct, PresentStrategy::Blit],
+            present_strategy => vec![present_strategy],
+        };
+        let (color_space, present_strategy, surface_format) = color_spaces
+            .into_iter()
+            .find_map(|color_space| {
+                present_strategies.iter().find_map(|&present_strategy| {
+                    choose_surface_format(
+                        instance,
+                        physical_device,
+                        &surface_formats,
+                        &surface_capabilities,
+                        &config.surface_formats,
+                        color_space,
+                        present_strategy,
+                    )
+                    .map(|surface_format| (color_space, present_strategy, surface_format))
+                })
+            })
+            .ok_or_else(|| {
+                let msg = format!(
+                    "None of the surface formats {:?} can be presented to using {:?}",
+                    config.surface_formats, config.present_strategy
+                );
+                Error::Local(msg)
+            })?;
+        if color_space != config.color_space {
+            warn!(
+                "No suitable surface format for {:?}, falling back to sRGB",
+                config.color_space
+            );
+        }
+        debug!(
+            "Using surface format {:?} {:?} with present strategy {present_strategy:?}",
+            surface_format.format, surface_format.color_space
+        );
 
         // For reference see:
         // https://www.reddit.com/r/vulkan/comments/9txqqb/what_is_presentation_mode/
 
-        let desired_present_mode = if vsync {
-            vk::PresentModeKHR::FIFO
+        let supported_present_modes = PresentMode::ALL
+            .into_iter()
+            .filter(|mode| present_modes.contains(&mode.vk_present_mode()))
+            .collect::<Vec<_>>();
+        let present_mode = config
+            .present_mode
+            .fallbacks()
+            .iter()
+            .copied()
+            .find(|mode| supported_present_modes.contains(mode))
+            .ok_or_else(|| Error::Local("There is no FIFO present mode".to_owned()))?;
+        if present_mode != config.present_mode {
+            warn!(
+                "Present mode {:?} is not supported, falling back to {present_mode:?}",
+                config.present_mode
+            );
+        }
+
+        // Transparent windows need the alpha channel, opaque ones should ignore it. At least one
+        // mode is always supported.
+        let supported_composite_alpha = surface_capabilities.supported_composite_alpha;
+        let preferred_composite_alpha = if config.transparent {
+            &[
+                vk::CompositeAlphaFlagsKHR::PRE_MULTIPLIED,
+                vk::CompositeAlphaFlagsKHR::POST_MULTIPLIED,
+                vk::CompositeAlphaFlagsKHR::INHERIT,
+                vk::CompositeAlphaFlagsKHR::OPAQUE,
+            ]
         } else {
-            vk::PresentModeKHR::IMMEDIATE
+            &[
+                vk::CompositeAlphaFlagsKHR::OPAQUE,
+                vk::CompositeAlphaFlagsKHR::INHERIT,
+                vk::CompositeAlphaFlagsKHR::PRE_MULTIPLIED,
+                vk::CompositeAlphaFlagsKHR::POST_MULTIPLIED,
+            ]
         };
-
-        let desired_present_mode = present_modes
-            .into_iter()
-            .find(|&mode| mode == desired_present_mode)
-            .ok_or_else(|| Error::Local("There is no vsync present mode".to_owned()))?;
-
-        // Check that the surface supports storage write/can be used in compute shaders.
-        if !surface_capabilities
-            .supported_usage_flags
-            .contains(vk::ImageUsageFlags::STORAGE | vk::ImageUsageFlags::SAMPLED)
-        {
-            return Err(Error::Local(
-                "Surface cannot be used for storage".to_owned(),
-            ));
+        let composite_alpha = preferred_composite_alpha
+            .iter()
+            .copied()
+            .find(|mode| supported_composite_alpha.contains(*mode))
+            .unwrap_or(vk::CompositeAlphaFlagsKHR::OPAQUE);
+        if config.transparent && composite_alpha == vk::CompositeAlphaFlagsKHR::OPAQUE {
+            warn!("Surface doesn't support transparency, alpha is ignored");
         }
 
         // Try to get triple buffering, fall back to double-buffering.
@@ -66,22 +222,46 @@ impl SurfaceInfo {
             desired_image_count = max_image_count;
         }
 
-        let mut surface_resolution = surface_capabilities.current_extent;
-        if surface_resolution.width == std::u32::MAX {
-            error!("Unexpected situation: {surface_capabilities:#?}");
-            warn!("Setting surface resolution to HD");
-            surface_resolution = vk::Extent2D {
-                width: 1280,
-                height: 720,
-            };
+        if scaled {
+            debug!("Rendering at {render_resolution:?}, upscaling to {surface_resolution:?}");
         }
 
         Ok(Self {
             surface_format,
+            color_space,
+            present_strategy,
             surface_capabilities,
-            desired_present_mode,
+            present_mode,
+            supported_present_modes,
+            composite_alpha,
             desired_image_count,
             surface_resolution,
+            render_resolution,
         })
     }
+
+    /// The current extent of the surface. Some platforms (e.g. Wayland) leave the extent up to
+    /// the swapchain, in which case `window_extent` is clamped to the supported range. A zero
+    /// extent means the window is minimized.
+    #[must_use]
+    pub fn surface_resolution(
+        surface_capabilities: &vk::SurfaceCapabilitiesKHR,
+        window_extent: vk::Extent2D,
+    ) -> vk::Extent2D {
+        let current_extent = surface_capabilities.current_extent;
+        if current_extent.width != u32::MAX {
+            return current_extent;
+        }
+
+        let min_extent = surface_capabilities.min_image_extent;
+        let max_extent = surface_capabilities.max_image_extent;
+        vk::Extent2D {
+            width: window_extent
+                .width
+                .clamp(min_extent.width, max_extent.width),
+            height: window_extent
+                .height
+                .clamp(min_extent.height, max_extent.height),
+        }
+    }
 }
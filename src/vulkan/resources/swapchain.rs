@@ -4,7 +4,7 @@ use ash::{extensions::khr::Swapchain as SwapchainLoader, vk};
 
 use log::debug;
 
-use crate::error::VResult;
+use crate::{error::VResult, vulkan::config::PresentStrategy};
 
 use super::{surface::Surface, surface_info::SurfaceInfo};
 
@@ -31,6 +31,16 @@ impl Swapchain {
         debug!("Creating swapchain");
         let swapchain_loader = swapchain_loader.clone();
         let surface_format = &surface_info.surface_format;
+        let image_usage = match surface_info.present_strategy {
+            PresentStrategy::Blit => {
+                vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_DST
+            }
+            PresentStrategy::Auto | PresentStrategy::Direct => {
+                vk::ImageUsageFlags::COLOR_ATTACHMENT
+                    | vk::ImageUsageFlags::STORAGE
+                    | vk::ImageUsageFlags::SAMPLED
+            }
+        };
 
         let swapchain_create_info = vk::SwapchainCreateInfoKHR::builder()
             .surface(**surface)
@@ -38,15 +48,11 @@ impl Swapchain {
             .image_color_space(surface_format.color_space)
             .image_format(surface_format.format)
             .image_extent(surface_info.surface_resolution)
-            .image_usage(
-                vk::ImageUsageFlags::COLOR_ATTACHMENT
-                    | vk::ImageUsageFlags::STORAGE
-                    | vk::ImageUsageFlags::SAMPLED,
-            )
+            .image_usage(image_usage)
             .image_sharing_mode(vk::SharingMode::EXCLUSIVE)
             .pre_transform(vk::SurfaceTransformFlagsKHR::IDENTITY)
-            .composite_alpha(vk::CompositeAlphaFlagsKHR::OPAQUE)
-            .present_mode(surface_info.desired_present_mode)
+            .composite_alpha(surface_info.composite_alpha)
+            .present_mode(surface_info.present_mode.vk_present_mode())
             .clipped(true)
             .image_array_layers(1)
             .old_swapchain(old_swapchain.unwrap_or(vk::SwapchainKHR::null()));
@@ -1,26 +1,182 @@
-use log::debug;
+use log::{debug, warn};
 use raw_window_handle::{HasRawDisplayHandle, HasRawWindowHandle};
-use winit::{dpi::PhysicalPosition, window::CursorGrabMode};
+use winit::{
+    dpi::{PhysicalPosition, PhysicalSize},
+    window::{CursorGrabMode, Fullscreen, Icon, WindowId, WindowLevel},
+};
 
-use crate::{error::VResult, event_loop::EventLoop, vulkan::resources::instance::Instance};
+use crate::{
+    error::{Error, VResult},
+    event_loop::EventLoop,
+    vulkan::resources::instance::Instance,
+};
 use ash::vk::SurfaceKHR as VkSurface;
 
-pub struct Window(winit::window::Window);
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FullscreenMode {
+    /// A borderless window covering the current monitor.
+    Borderless,
+    /// Switch the current monitor to its largest video mode with the highest refresh rate.
+    Exclusive,
+}
+
+/// Window creation options, see `Window::with_config`.
+///
+/// ```ignore
+/// let config = WindowConfig::default()
+///     .title("raymarcher")
+///     .inner_size(1280, 720)
+///     .fullscreen(Some(FullscreenMode::Borderless));
+/// let window = Window::with_config(&event_loop, &config)?;
+/// ```
+#[derive(Clone)]
+pub struct WindowConfig {
+    pub title: String,
+    /// Inner size in physical pixels, `None` lets the platform decide.
+    pub inner_size: Option<(u32, u32)>,
+    pub min_inner_size: Option<(u32, u32)>,
+    pub max_inner_size: Option<(u32, u32)>,
+    pub resizable: bool,
+    pub fullscreen: Option<FullscreenMode>,
+    pub decorations: bool,
+    pub always_on_top: bool,
+    /// The desktop shows through where the presented alpha is below 1, if the surface supports
+    /// a non-opaque composite alpha mode.
+    pub transparent: bool,
+    pub icon: Option<Icon>,
+}
+
+impl Default for WindowConfig {
+    fn default() -> Self {
+        Self {
+            title: "visualize-rs".to_owned(),
+            inner_size: None,
+            min_inner_size: None,
+            max_inner_size: None,
+            resizable: true,
+            fullscreen: None,
+            decorations: true,
+            always_on_top: false,
+            transparent: false,
+            icon: None,
+        }
+    }
+}
+
+impl WindowConfig {
+    #[must_use]
+    pub fn title(mut self, title: &str) -> Self {
+        self.title = title.to_owned();
+        self
+    }
+
+    #[must_use]
+    pub fn inner_size(mut self, width: u32, height: u32) -> Self {
+        self.inner_size = Some((width, height));
+        self
+    }
+
+    #[must_use]
+    pub fn min_inner_size(mut self, width: u32, height: u32) -> Self {
+        self.min_inner_size = Some((width, height));
+        self
+    }
+
+    #[must_use]
+    pub fn max_inner_size(mut self, width: u32, height: u32) -> Self {
+        self.max_inner_size = Some((width, height));
+        self
+    }
+
+    #[must_use]
+    pub fn resizable(mut self, resizable: bool) -> Self {
+        self.resizable = resizable;
+        self
+    }
+
+    #[must_use]
+    pub fn fullscreen(mut self, fullscreen: Option<FullscreenMode>) -> Self {
+        self.fullscreen = fullscreen;
+        self
+    }
+
+    #[must_use]
+    pub fn decorations(mut self, decorations: bool) -> Self {
+        self.decorations = decorations;
+        self
+    }
+
+    #[must_use]
+    pub fn always_on_top(mut self, always_on_top: bool) -> Self {
+        self.always_on_top = always_on_top;
+        self
+    }
+
+    #[must_use]
+    pub fn transparent(mut self, transparent: bool) -> Self {
+        self.transparent = transparent;
+        self
+    }
+
+    /// Window icon from RGBA8 pixels.
+    pub fn icon(mut self, rgba: Vec<u8>, width: u32, height: u32) -> VResult<Self> {
+        let icon = Icon::from_rgba(rgba, width, height)
+            .map_err(|err| Error::Local(format!("Invalid window icon: {err}")))?;
+        self.icon = Some(icon);
+        Ok(self)
+    }
+}
+
+fn window_level(always_on_top: bool) -> WindowLevel {
+    if always_on_top {
+        WindowLevel::AlwaysOnTop
+    } else {
+        WindowLevel::Normal
+    }
+}
+
+pub struct Window {
+    window: winit::window::Window,
+    transparent: bool,
+}
 
 impl Window {
     pub fn new(event_loop: &EventLoop, resizeable: bool) -> VResult<Self> {
+        Self::with_config(event_loop, &WindowConfig::default().resizable(resizeable))
+    }
+
+    pub fn with_config(event_loop: &EventLoop, config: &WindowConfig) -> VResult<Self> {
         debug!("Initializing video system");
 
-        let window = winit::window::WindowBuilder::new()
-            .with_resizable(resizeable)
-            .with_title("visualize-rs")
-            .build(event_loop)?;
+        let mut builder = winit::window::WindowBuilder::new()
+            .with_title(&config.title)
+            .with_resizable(config.resizable)
+            .with_decorations(config.decorations)
+            .with_window_level(window_level(config.always_on_top))
+            .with_transparent(config.transparent)
+            .with_window_icon(config.icon.clone());
+        if let Some((width, height)) = config.inner_size {
+            builder = builder.with_inner_size(PhysicalSize::new(width, height));
+        }
+        if let Some((width, height)) = config.min_inner_size {
+            builder = builder.with_min_inner_size(PhysicalSize::new(width, height));
+        }
+        if let Some((width, height)) = config.max_inner_size {
+            builder = builder.with_max_inner_size(PhysicalSize::new(width, height));
+        }
+        let window = builder.build(event_loop)?;
 
-        Ok(Self(window))
+        let window = Self {
+            window,
+            transparent: config.transparent,
+        };
+        // Exclusive fullscreen needs the monitor the window was created on.
+        window.set_fullscreen(config.fullscreen);
+        Ok(window)
     }
 
     pub fn enumerate_required_extensions(&self) -> VResult<Vec<*const i8>> {
-        let raw_handle = self.0.raw_display_handle();
+        let raw_handle = self.window.raw_display_handle();
         let extensions = ash_window::enumerate_required_extensions(raw_handle)?;
         Ok(extensions.to_vec())
     }
@@ -30,8 +186,8 @@ impl Window {
             Ok(ash_window::create_surface(
                 entry,
                 instance,
-                self.0.raw_display_handle(),
-                self.0.raw_window_handle(),
+                self.window.raw_display_handle(),
+                self.window.raw_window_handle(),
                 None,
             )?)
         }
@@ -39,21 +195,87 @@ impl Window {
 
     pub fn set_cursor_grab(&self, lock: bool) {
         let _ = if lock {
-            self.0
+            self.window
                 .set_cursor_grab(CursorGrabMode::Confined)
-                .or_else(|_| self.0.set_cursor_grab(CursorGrabMode::Locked))
+                .or_else(|_| self.window.set_cursor_grab(CursorGrabMode::Locked))
         } else {
-            self.0.set_cursor_grab(CursorGrabMode::None)
+            self.window.set_cursor_grab(CursorGrabMode::None)
         }
-        .map(|_| self.0.set_cursor_visible(!lock));
+        .map(|_| self.window.set_cursor_visible(!lock));
     }
 
     pub fn set_cursor_position(&self, x: u32, y: u32) {
-        let _ = self.0.set_cursor_position(PhysicalPosition::new(x, y));
+        let _ = self.window.set_cursor_position(PhysicalPosition::new(x, y));
+    }
+
+    /// Tells windows apart in `App::handle_window_event` and `Vulkan::add_window`.
+    #[must_use]
+    pub fn id(&self) -> WindowId {
+        self.window.id()
     }
 
     pub fn size(&self) -> (u32, u32) {
-        let size = self.0.inner_size();
+        let size = self.window.inner_size();
         (size.width, size.height)
     }
+
+    /// E.g. to show the FPS or the shader compile status.
+    pub fn set_title(&self, title: &str) {
+        self.window.set_title(title);
+    }
+
+    /// Enter or leave fullscreen. Falls back to borderless if exclusive fullscreen has no video
+    /// mode.
+    pub fn set_fullscreen(&self, fullscreen: Option<FullscreenMode>) {
+        let monitor = self.window.current_monitor();
+        let fullscreen = match fullscreen {
+            None => None,
+            Some(FullscreenMode::Borderless) => Some(Fullscreen::Borderless(monitor)),
+            Some(FullscreenMode::Exclusive) => {
+                let video_mode = monitor.as_ref().and_then(|monitor| {
+                    monitor.video_modes().max_by_key(|mode| {
+                        let size = mode.size();
+                        (
+                            size.width * size.height,
+                            mode.refresh_rate_millihertz(),
+                            mode.bit_depth(),
+                        )
+                    })
+                });
+                if let Some(video_mode) = video_mode {
+                    Some(Fullscreen::Exclusive(video_mode))
+                } else {
+                    warn!("No video mode for exclusive fullscreen, using borderless");
+                    Some(Fullscreen::Borderless(monitor))
+                }
+            }
+        };
+        self.window.set_fullscreen(fullscreen);
+    }
+
+    #[must_use]
+    pub fn fullscreen(&self) -> Option<FullscreenMode> {
+        self.window.fullscreen().map(|fullscreen| match fullscreen {
+            Fullscreen::Borderless(_) => FullscreenMode::Borderless,
+            Fullscreen::Exclusive(_) => FullscreenMode::Exclusive,
+        })
+    }
+
+    /// Switch between windowed and `mode`, e.g. bound to F11.
+    pub fn toggle_fullscreen(&self, mode: FullscreenMode) {
+        if self.fullscreen().is_some() {
+            self.set_fullscreen(None);
+        } else {
+            self.set_fullscreen(Some(mode));
+        }
+    }
+
+    pub fn set_always_on_top(&self, always_on_top: bool) {
+        self.window.set_window_level(window_level(always_on_top));
+    }
+
+    #[must_use]
+    pub fn is_transparent(&self) -> bool {
+        self.transparent
+    }
 }